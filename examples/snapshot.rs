@@ -0,0 +1,34 @@
+use ipi_game::raster;
+use ipi_game::universe::Universe;
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <output.png|output.svg> [seed] [width] [height] [ticks]",
+            args[0]
+        );
+        process::exit(1);
+    }
+    let arg = |index: usize, default: u32| {
+        args.get(index)
+            .map(|value| value.parse().expect("expected a number"))
+            .unwrap_or(default)
+    };
+    let mut universe = Universe::with_seed(arg(3, 1280), arg(4, 720), arg(2, 1));
+    for _ in 0..arg(5, 0) {
+        universe.tick();
+    }
+    let result = if args[1].ends_with(".svg") {
+        fs::write(&args[1], universe.to_svg())
+    } else {
+        raster::render(&universe).write_png(&args[1])
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to write {}: {}", args[1], err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,615 @@
+use std::f64::consts::PI;
+use wasm_bindgen::prelude::*;
+
+const KEPLER_ITERATIONS: u32 = 8;
+
+/// Handle to a star in [`Bodies`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StarId(u32);
+
+/// Handle to a planet in [`Bodies`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlanetId(u32);
+
+impl StarId {
+    pub(crate) fn new(index: usize) -> StarId {
+        StarId(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl PlanetId {
+    pub(crate) fn new(index: usize) -> PlanetId {
+        PlanetId(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Schedule of the times a planet has a link to other planets, like a
+/// contact plan of a delay-tolerant network. Planets can only capture and
+/// send on packets while online.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkWindow {
+    Always,
+    /// Online for the first `online` ticks of every `period` ticks, shifted
+    /// by `phase` ticks.
+    Periodic {
+        period: u32,
+        online: u32,
+        phase: u32,
+    },
+    /// Online while the mean anomaly of the planet is within `length`
+    /// degrees after `start`.
+    Orbit {
+        start: f64,
+        length: f64,
+    },
+}
+
+/// Kind of a planet, which decides what happens to a packet it captures.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlanetKind {
+    Normal,
+    /// Sends packets on faster than they arrived.
+    Amplifier,
+    /// Re-emits packets at the mirrored angle as soon as they arrive.
+    Reflector,
+    /// Spins packets against its own direction of orbit.
+    ReverseSpin,
+    /// Swallows packets.
+    Sink,
+    /// Recharges the energy of the round when it captures the packet.
+    Charger,
+}
+
+/// Capture behavior of a planet kind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KindRules {
+    /// Whether the release speed is multiplied by the amplifier boost.
+    pub boosts: bool,
+    pub reflects: bool,
+    pub reverses_spin: bool,
+    pub swallows: bool,
+    pub recharges: bool,
+}
+
+const KIND_RULES: [KindRules; 6] = [
+    // Normal
+    KindRules {
+        boosts: false,
+        reflects: false,
+        reverses_spin: false,
+        swallows: false,
+        recharges: false,
+    },
+    // Amplifier
+    KindRules {
+        boosts: true,
+        reflects: false,
+        reverses_spin: false,
+        swallows: false,
+        recharges: false,
+    },
+    // Reflector
+    KindRules {
+        boosts: false,
+        reflects: true,
+        reverses_spin: false,
+        swallows: false,
+        recharges: false,
+    },
+    // ReverseSpin
+    KindRules {
+        boosts: false,
+        reflects: false,
+        reverses_spin: true,
+        swallows: false,
+        recharges: false,
+    },
+    // Sink
+    KindRules {
+        boosts: false,
+        reflects: false,
+        reverses_spin: false,
+        swallows: true,
+        recharges: false,
+    },
+    // Charger
+    KindRules {
+        boosts: false,
+        reflects: false,
+        reverses_spin: false,
+        swallows: false,
+        recharges: true,
+    },
+];
+
+impl PlanetKind {
+    pub fn rules(self) -> &'static KindRules {
+        &KIND_RULES[self as usize]
+    }
+}
+
+/// Registry of the stars and planets of a universe, stored as a struct of
+/// arrays indexed by [`StarId`] and [`PlanetId`].
+///
+/// Moons are planets that orbit another planet instead of their star. They
+/// belong to the system of the star their parent belongs to.
+///
+/// Orbits are Kepler ellipses with the orbited body at one focus. The angle
+/// of a planet is its mean anomaly, which advances at a constant rate, so
+/// planets move faster near periapsis than near apoapsis.
+///
+/// Removing a body moves the last body of the same kind into its slot, so
+/// removal returns the id that the moved body had before.
+#[derive(Default)]
+pub struct Bodies {
+    star_x: Vec<u32>,
+    star_y: Vec<u32>,
+    star_radius: Vec<u32>,
+    star_system_radius: Vec<u32>,
+    planet_star: Vec<StarId>,
+    planet_parent: Vec<Option<PlanetId>>,
+    planet_radius: Vec<u32>,
+    planet_distance: Vec<u32>,
+    planet_eccentricity: Vec<f64>,
+    planet_periapsis: Vec<f64>,
+    planet_q: Vec<f64>,
+    planet_dq: Vec<f64>,
+    planet_direction: Vec<bool>,
+    planet_queue_capacity: Vec<u32>,
+    planet_processing_delay: Vec<u32>,
+    planet_link_window: Vec<LinkWindow>,
+    planet_kind: Vec<PlanetKind>,
+}
+
+impl Bodies {
+    pub fn new() -> Bodies {
+        Bodies::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.clear_stars();
+        self.truncate_planets(0);
+    }
+
+    pub(crate) fn clear_stars(&mut self) {
+        self.star_x.clear();
+        self.star_y.clear();
+        self.star_radius.clear();
+        self.star_system_radius.clear();
+    }
+
+    pub(crate) fn truncate_planets(&mut self, len: usize) {
+        self.planet_star.truncate(len);
+        self.planet_parent.truncate(len);
+        self.planet_radius.truncate(len);
+        self.planet_distance.truncate(len);
+        self.planet_eccentricity.truncate(len);
+        self.planet_periapsis.truncate(len);
+        self.planet_q.truncate(len);
+        self.planet_dq.truncate(len);
+        self.planet_direction.truncate(len);
+        self.planet_queue_capacity.truncate(len);
+        self.planet_processing_delay.truncate(len);
+        self.planet_link_window.truncate(len);
+        self.planet_kind.truncate(len);
+    }
+
+    pub fn num_stars(&self) -> usize {
+        self.star_x.len()
+    }
+
+    pub fn num_planets(&self) -> usize {
+        self.planet_star.len()
+    }
+
+    pub fn star_ids(&self) -> impl Iterator<Item = StarId> {
+        (0..self.num_stars()).map(StarId::new)
+    }
+
+    pub fn planet_ids(&self) -> impl Iterator<Item = PlanetId> {
+        (0..self.num_planets()).map(PlanetId::new)
+    }
+
+    pub fn stars(&self) -> impl Iterator<Item = Star<'_>> {
+        self.star_ids().map(move |id| self.star(id))
+    }
+
+    pub fn planets(&self) -> impl Iterator<Item = Planet<'_>> {
+        self.planet_ids().map(move |id| self.planet(id))
+    }
+
+    pub fn star(&self, id: StarId) -> Star<'_> {
+        Star { bodies: self, id }
+    }
+
+    pub fn planet(&self, id: PlanetId) -> Planet<'_> {
+        Planet { bodies: self, id }
+    }
+
+    pub fn add_star(&mut self, x: u32, y: u32, radius: u32, system_radius: u32) -> StarId {
+        self.star_x.push(x);
+        self.star_y.push(y);
+        self.star_radius.push(radius);
+        self.star_system_radius.push(system_radius);
+        StarId::new(self.num_stars() - 1)
+    }
+
+    /// Adds a planet on a circular orbit around `star`. The planet does not
+    /// move until its motion is set with [`Bodies::set_motion`]. It is a
+    /// normal planet that is always online and routes packets of any size
+    /// without delay, until set otherwise.
+    pub fn add_planet(&mut self, star: StarId, radius: u32, distance: u32) -> PlanetId {
+        self.push_planet(star, None, radius, distance)
+    }
+
+    /// Adds a moon orbiting `parent`, which does not move until its motion
+    /// is set like for a planet.
+    pub fn add_moon(&mut self, parent: PlanetId, radius: u32, distance: u32) -> PlanetId {
+        let star = self.planet(parent).star_id();
+        self.push_planet(star, Some(parent), radius, distance)
+    }
+
+    fn push_planet(
+        &mut self,
+        star: StarId,
+        parent: Option<PlanetId>,
+        radius: u32,
+        distance: u32,
+    ) -> PlanetId {
+        self.planet_star.push(star);
+        self.planet_parent.push(parent);
+        self.planet_radius.push(radius);
+        self.planet_distance.push(distance);
+        self.planet_eccentricity.push(0.0);
+        self.planet_periapsis.push(0.0);
+        self.planet_q.push(0.0);
+        self.planet_dq.push(0.0);
+        self.planet_direction.push(true);
+        self.planet_queue_capacity.push(u32::MAX);
+        self.planet_processing_delay.push(0);
+        self.planet_link_window.push(LinkWindow::Always);
+        self.planet_kind.push(PlanetKind::Normal);
+        PlanetId::new(self.num_planets() - 1)
+    }
+
+    /// Sets the eccentricity of the orbit of a planet, between 0 for a
+    /// circle and 1 exclusive, and the angle of its periapsis in degrees.
+    pub fn set_orbit(&mut self, planet: PlanetId, eccentricity: f64, periapsis: f64) {
+        let index = planet.index();
+        self.planet_eccentricity[index] = eccentricity.clamp(0.0, 0.99);
+        self.planet_periapsis[index] = periapsis;
+    }
+
+    /// Sets the mean anomaly of a planet on its orbit, and the mean speed
+    /// and direction it moves along the orbit in each tick.
+    pub fn set_motion(&mut self, planet: PlanetId, q: f64, dq: f64, direction: bool) {
+        let index = planet.index();
+        self.planet_q[index] = q;
+        self.planet_dq[index] = dq;
+        self.planet_direction[index] = direction;
+    }
+
+    /// Sets the largest payload a planet can queue, and the ticks it takes to
+    /// process a packet before it can be sent on.
+    pub fn set_router(&mut self, planet: PlanetId, queue_capacity: u32, processing_delay: u32) {
+        let index = planet.index();
+        self.planet_queue_capacity[index] = queue_capacity;
+        self.planet_processing_delay[index] = processing_delay;
+    }
+
+    pub fn set_link_window(&mut self, planet: PlanetId, window: LinkWindow) {
+        self.planet_link_window[planet.index()] = window;
+    }
+
+    pub fn set_kind(&mut self, planet: PlanetId, kind: PlanetKind) {
+        self.planet_kind[planet.index()] = kind;
+    }
+
+    /// Moves every planet one tick along its orbit.
+    pub fn advance_orbits(&mut self) {
+        for planet in 0..self.num_planets() {
+            if self.planet_direction[planet] {
+                self.planet_q[planet] += self.planet_dq[planet];
+            } else {
+                self.planet_q[planet] += 360.0 - self.planet_dq[planet];
+            }
+            if self.planet_q[planet] > 360.0 {
+                self.planet_q[planet] -= 360.0;
+            }
+        }
+    }
+
+    /// Removes a planet, which must not have any moons left. Returns the
+    /// previous id of the planet that was moved into the slot of the removed
+    /// planet, if any.
+    pub fn remove_planet(&mut self, planet: PlanetId) -> Option<PlanetId> {
+        debug_assert!(self
+            .planet_parent
+            .iter()
+            .all(|parent| *parent != Some(planet)));
+        let index = planet.index();
+        self.planet_star.swap_remove(index);
+        self.planet_parent.swap_remove(index);
+        self.planet_radius.swap_remove(index);
+        self.planet_distance.swap_remove(index);
+        self.planet_eccentricity.swap_remove(index);
+        self.planet_periapsis.swap_remove(index);
+        self.planet_q.swap_remove(index);
+        self.planet_dq.swap_remove(index);
+        self.planet_direction.swap_remove(index);
+        self.planet_queue_capacity.swap_remove(index);
+        self.planet_processing_delay.swap_remove(index);
+        self.planet_link_window.swap_remove(index);
+        self.planet_kind.swap_remove(index);
+        if index < self.num_planets() {
+            let moved = PlanetId::new(self.num_planets());
+            for parent in self.planet_parent.iter_mut() {
+                if *parent == Some(moved) {
+                    *parent = Some(planet);
+                }
+            }
+            Some(moved)
+        } else {
+            None
+        }
+    }
+
+    /// Removes a star, which must not have any planets left. Returns the
+    /// previous id of the star that was moved into the slot of the removed
+    /// star, if any.
+    pub fn remove_star(&mut self, star: StarId) -> Option<StarId> {
+        debug_assert!(self
+            .planet_star
+            .iter()
+            .all(|planet_star| *planet_star != star));
+        let index = star.index();
+        self.star_x.swap_remove(index);
+        self.star_y.swap_remove(index);
+        self.star_radius.swap_remove(index);
+        self.star_system_radius.swap_remove(index);
+        if index < self.num_stars() {
+            let moved = StarId::new(self.num_stars());
+            for planet_star in self.planet_star.iter_mut() {
+                if *planet_star == moved {
+                    *planet_star = star;
+                }
+            }
+            Some(moved)
+        } else {
+            None
+        }
+    }
+}
+
+/// View of a star in [`Bodies`].
+#[derive(Clone, Copy)]
+pub struct Star<'a> {
+    bodies: &'a Bodies,
+    id: StarId,
+}
+
+impl<'a> Star<'a> {
+    pub fn id(&self) -> StarId {
+        self.id
+    }
+
+    pub fn x(&self) -> u32 {
+        self.bodies.star_x[self.id.index()]
+    }
+
+    pub fn y(&self) -> u32 {
+        self.bodies.star_y[self.id.index()]
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.bodies.star_radius[self.id.index()]
+    }
+
+    /// Radius of the star system, which contains the orbits of all its
+    /// planets.
+    pub fn system_radius(&self) -> u32 {
+        self.bodies.star_system_radius[self.id.index()]
+    }
+
+    /// Returns the planets of the star system, including moons.
+    pub fn planets(&self) -> impl Iterator<Item = Planet<'a>> {
+        let (bodies, id) = (self.bodies, self.id);
+        bodies
+            .planets()
+            .filter(move |planet| planet.star_id() == id)
+    }
+}
+
+/// View of a planet in [`Bodies`].
+#[derive(Clone, Copy)]
+pub struct Planet<'a> {
+    bodies: &'a Bodies,
+    id: PlanetId,
+}
+
+impl<'a> Planet<'a> {
+    pub fn id(&self) -> PlanetId {
+        self.id
+    }
+
+    pub fn star_id(&self) -> StarId {
+        self.bodies.planet_star[self.id.index()]
+    }
+
+    pub fn star(&self) -> Star<'a> {
+        self.bodies.star(self.star_id())
+    }
+
+    /// Returns the planet this moon orbits, or `None` for a planet that
+    /// orbits its star.
+    pub fn parent(&self) -> Option<Planet<'a>> {
+        self.bodies.planet_parent[self.id.index()].map(|parent| self.bodies.planet(parent))
+    }
+
+    pub fn is_moon(&self) -> bool {
+        self.bodies.planet_parent[self.id.index()].is_some()
+    }
+
+    pub fn moons(&self) -> impl Iterator<Item = Planet<'a>> {
+        let (bodies, id) = (self.bodies, self.id);
+        bodies
+            .planets()
+            .filter(move |planet| bodies.planet_parent[planet.id.index()] == Some(id))
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.bodies.planet_radius[self.id.index()]
+    }
+
+    /// Semi-major axis of the orbit of the planet around its star, or of the
+    /// moon around its parent.
+    pub fn distance(&self) -> u32 {
+        self.bodies.planet_distance[self.id.index()]
+    }
+
+    pub fn eccentricity(&self) -> f64 {
+        self.bodies.planet_eccentricity[self.id.index()]
+    }
+
+    /// Angle of the point of the orbit closest to the orbited body, in
+    /// degrees.
+    pub fn periapsis(&self) -> f64 {
+        self.bodies.planet_periapsis[self.id.index()]
+    }
+
+    pub fn semi_minor_axis(&self) -> f64 {
+        let eccentricity = self.eccentricity();
+        self.distance() as f64 * (1.0 - eccentricity * eccentricity).sqrt()
+    }
+
+    /// Closest and furthest distance of the planet from the orbited body.
+    pub fn distance_range(&self) -> (f64, f64) {
+        let distance = self.distance() as f64;
+        (
+            distance * (1.0 - self.eccentricity()),
+            distance * (1.0 + self.eccentricity()),
+        )
+    }
+
+    /// Returns the smallest and largest distance of the planet from its
+    /// star, over all positions of its orbit and those of its parents.
+    pub fn reach_range(&self) -> (f64, f64) {
+        let (near, far) = self.distance_range();
+        match self.parent() {
+            Some(parent) => {
+                let (parent_near, parent_far) = parent.reach_range();
+                ((parent_near - far).max(0.0), parent_far + far)
+            }
+            None => (near, far),
+        }
+    }
+
+    /// Mean anomaly of the planet on its orbit, in degrees.
+    pub fn q(&self) -> f64 {
+        self.bodies.planet_q[self.id.index()]
+    }
+
+    /// Degrees the mean anomaly of the planet advances in each tick.
+    pub fn dq(&self) -> f64 {
+        self.bodies.planet_dq[self.id.index()]
+    }
+
+    pub fn direction(&self) -> bool {
+        self.bodies.planet_direction[self.id.index()]
+    }
+
+    /// Largest payload the planet can queue. Larger packets are dropped.
+    pub fn queue_capacity(&self) -> u32 {
+        self.bodies.planet_queue_capacity[self.id.index()]
+    }
+
+    /// Ticks the planet holds a packet before it can be sent on.
+    pub fn processing_delay(&self) -> u32 {
+        self.bodies.planet_processing_delay[self.id.index()]
+    }
+
+    pub fn kind(&self) -> PlanetKind {
+        self.bodies.planet_kind[self.id.index()]
+    }
+
+    pub fn link_window(&self) -> LinkWindow {
+        self.bodies.planet_link_window[self.id.index()]
+    }
+
+    /// Returns whether the planet has a link at tick `time`.
+    pub fn is_online(&self, time: u32) -> bool {
+        match self.link_window() {
+            LinkWindow::Always => true,
+            LinkWindow::Periodic {
+                period,
+                online,
+                phase,
+            } => (time.wrapping_add(phase)) % period.max(1) < online,
+            LinkWindow::Orbit { start, length } => (self.q() - start).rem_euclid(360.0) < length,
+        }
+    }
+
+    /// Returns the position of the body the planet orbits.
+    pub fn orbit_center(&self) -> (f64, f64) {
+        match self.parent() {
+            Some(parent) => (parent.x(), parent.y()),
+            None => {
+                let star = self.star();
+                (star.x() as f64, star.y() as f64)
+            }
+        }
+    }
+
+    /// Returns the center of the orbit ellipse, which is offset from the
+    /// orbited body away from periapsis.
+    pub fn ellipse_center(&self) -> (f64, f64) {
+        let (x, y) = self.orbit_center();
+        let offset = self.distance() as f64 * self.eccentricity();
+        let periapsis = self.periapsis().to_radians();
+        (x - offset * periapsis.cos(), y - offset * periapsis.sin())
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        let (x, y) = self.orbit_center();
+        let distance = self.distance() as f64;
+        let eccentricity = self.eccentricity();
+        let anomaly = eccentric_anomaly(self.q().to_radians(), eccentricity);
+        // Position relative to the focus, with periapsis along the x axis
+        let px = distance * (anomaly.cos() - eccentricity);
+        let py = self.semi_minor_axis() * anomaly.sin();
+        let (sin, cos) = self.periapsis().to_radians().sin_cos();
+        (x + px * cos - py * sin, y + px * sin + py * cos)
+    }
+
+    pub fn x(&self) -> f64 {
+        self.position().0
+    }
+
+    pub fn y(&self) -> f64 {
+        self.position().1
+    }
+}
+
+/// Solves Kepler's equation `M = E - e sin E` for the eccentric anomaly `E`
+/// with Newton's method, in radians.
+fn eccentric_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    if eccentricity == 0.0 {
+        return mean_anomaly;
+    }
+    let mean_anomaly = mean_anomaly.rem_euclid(2.0 * PI);
+    let mut anomaly = if eccentricity > 0.8 { PI } else { mean_anomaly };
+    for _ in 0..KEPLER_ITERATIONS {
+        anomaly -= (anomaly - eccentricity * anomaly.sin() - mean_anomaly)
+            / (1.0 - eccentricity * anomaly.cos());
+    }
+    anomaly
+}
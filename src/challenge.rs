@@ -0,0 +1,247 @@
+use crate::config::GameConfig;
+use crate::mode::GameMode;
+use crate::universe::Universe;
+use wasm_bindgen::prelude::*;
+
+/// Size of the world of a challenge, which is the same for every player so
+/// that everybody gets the same universes.
+pub const CHALLENGE_WIDTH: u32 = 1600;
+pub const CHALLENGE_HEIGHT: u32 = 900;
+const DAILY_SALT: u32 = 0x1b87_3593;
+
+/// Action of the player, recorded with the tick it happened at so that a
+/// game can be re-simulated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    FreePacket,
+    ReverseSpin,
+    SpeedUpSpin,
+    SlowDownSpin,
+    HoldSpin,
+    ReleaseSpin,
+    EndGame,
+}
+
+const INPUTS: [Input; 7] = [
+    Input::FreePacket,
+    Input::ReverseSpin,
+    Input::SpeedUpSpin,
+    Input::SlowDownSpin,
+    Input::HoldSpin,
+    Input::ReleaseSpin,
+    Input::EndGame,
+];
+
+impl Input {
+    pub fn from_code(code: u32) -> Option<Input> {
+        INPUTS.get(code as usize).copied()
+    }
+
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Rules of every challenge: a time attack with the default settings.
+pub fn challenge_config() -> GameConfig {
+    GameConfig {
+        mode: GameMode::TimeAttack,
+        ..GameConfig::default()
+    }
+}
+
+/// Returns the seed of the challenge of a day, counted in days since the
+/// Unix epoch.
+#[wasm_bindgen]
+pub fn daily_seed(day: u32) -> u32 {
+    mix(day ^ DAILY_SALT)
+}
+
+/// Result of a challenge that players can share, as seed, score and shots
+/// with a checksum against typos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResultCode {
+    pub seed: u32,
+    pub score: u32,
+    pub shots: u32,
+}
+
+impl ResultCode {
+    fn checksum(&self) -> u32 {
+        mix(self.seed ^ mix(self.score ^ mix(self.shots))) & 0xffff
+    }
+
+    /// Encodes the result as hexadecimal fields, e.g. `1F2E3D4C-7-2A-91B0`.
+    pub fn encode(&self) -> String {
+        format!(
+            "{:08X}-{:X}-{:X}-{:04X}",
+            self.seed,
+            self.score,
+            self.shots,
+            self.checksum()
+        )
+    }
+
+    /// Parses a code made by [`ResultCode::encode`], or returns `None` if
+    /// it is malformed or its checksum does not match.
+    pub fn parse(code: &str) -> Option<ResultCode> {
+        let mut fields = code
+            .trim()
+            .split('-')
+            .map(|field| u32::from_str_radix(field, 16));
+        let result = ResultCode {
+            seed: fields.next()?.ok()?,
+            score: fields.next()?.ok()?,
+            shots: fields.next()?.ok()?,
+        };
+        let checksum = fields.next()?.ok()?;
+        if fields.next().is_some() || checksum != result.checksum() {
+            return None;
+        }
+        Some(result)
+    }
+}
+
+/// Checks a shared result code by re-simulating the recorded inputs of the
+/// game, as returned by [`Universe::replay`].
+#[wasm_bindgen]
+pub fn verify_result(code: &str, replay: &[u32]) -> bool {
+    let result = match ResultCode::parse(code) {
+        Some(result) => result,
+        None => return false,
+    };
+    if !replay.len().is_multiple_of(2) {
+        return false;
+    }
+    let mut universe = match Universe::try_create_challenge(result.seed) {
+        Ok(universe) => universe,
+        Err(_) => return false,
+    };
+    // Inputs out of order are never applied, which fails the check
+    let mut inputs = replay.chunks(2).peekable();
+    while !universe.is_over() {
+        while let Some(input) = inputs.peek() {
+            if input[0] != universe.game_ticks() {
+                break;
+            }
+            match Input::from_code(input[1]) {
+                Some(input) => universe.apply_input(input),
+                None => return false,
+            }
+            inputs.next();
+        }
+        if universe.is_over() {
+            break;
+        }
+        universe.advance();
+    }
+    match universe.results() {
+        Some(results) => {
+            inputs.next().is_none()
+                && results.score == result.score
+                && results.shots == result.shots
+        }
+        None => false,
+    }
+}
+
+/// Mixes the bits of a value, so that neighbouring values give unrelated
+/// results.
+fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a challenge with a fixed script of inputs until it ends, at
+    /// the given time scale.
+    fn play(universe: &mut Universe, time_scale: f64) -> String {
+        universe.set_time_scale(time_scale);
+        let mut last_tick = None;
+        while !universe.is_over() {
+            let tick = universe.game_ticks();
+            if last_tick != Some(tick) {
+                last_tick = Some(tick);
+                match tick {
+                    1500 => universe.end_game(),
+                    _ if tick % 300 == 150 => universe.reverse_spin(),
+                    _ if tick.is_multiple_of(40) => universe.free_packet(),
+                    _ => {}
+                }
+            }
+            universe.tick();
+        }
+        universe.result_code().unwrap()
+    }
+
+    #[test]
+    fn result_code_round_trip() {
+        let result = ResultCode {
+            seed: 0x1f2e_3d4c,
+            score: 7,
+            shots: 42,
+        };
+        assert_eq!(ResultCode::parse(&result.encode()), Some(result));
+        assert_eq!(
+            ResultCode::parse(&format!(" {} ", result.encode())),
+            Some(result)
+        );
+    }
+
+    #[test]
+    fn result_code_rejects_bad_checksum() {
+        let code = ResultCode {
+            seed: 1,
+            score: 2,
+            shots: 3,
+        }
+        .encode();
+        let (fields, checksum) = code.split_at(code.len() - 4);
+        let checksum = u32::from_str_radix(checksum, 16).unwrap() ^ 1;
+        assert_eq!(
+            ResultCode::parse(&format!("{}{:04X}", fields, checksum)),
+            None
+        );
+        assert_eq!(ResultCode::parse(&format!("{}-0", code)), None);
+        assert_eq!(ResultCode::parse("1-2-3"), None);
+    }
+
+    #[test]
+    fn verifies_replay() {
+        let mut universe = Universe::challenge(daily_seed(1));
+        let code = play(&mut universe, 1.0);
+        assert!(ResultCode::parse(&code).unwrap().shots > 0);
+        assert!(verify_result(&code, &universe.replay()));
+
+        let mut forged = ResultCode::parse(&code).unwrap();
+        forged.score += 1;
+        assert!(!verify_result(&forged.encode(), &universe.replay()));
+        let mut replay = universe.replay();
+        replay.drain(..2);
+        assert!(!verify_result(&code, &replay));
+    }
+
+    #[test]
+    fn verifies_replay_in_slow_motion() {
+        let mut universe = Universe::challenge(daily_seed(2));
+        let code = play(&mut universe, 0.3);
+        assert!(verify_result(&code, &universe.replay()));
+    }
+
+    #[test]
+    fn verifies_replay_after_new_game() {
+        let mut universe = Universe::challenge(daily_seed(3));
+        let first = play(&mut universe, 1.0);
+        universe.new_game();
+        let second = play(&mut universe, 1.0);
+        assert_eq!(first, second);
+        assert!(verify_result(&second, &universe.replay()));
+    }
+}
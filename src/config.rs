@@ -0,0 +1,249 @@
+use crate::generator::GeneratorKind;
+use crate::mode::{Difficulty, GameMode};
+use wasm_bindgen::prelude::*;
+
+/// Tunable rules of the game, used to generate rounds and move the packet.
+///
+/// From JS, create one with `new GameConfig()`, set the fields to change,
+/// and pass it to `Universe.with_config`, or to `Universe.set_config` to
+/// change the rules of a running game.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameConfig {
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+    pub generator: GeneratorKind,
+    pub min_num_stars: u32,
+    pub max_num_stars: u32,
+    pub min_star_radius: u32,
+    pub max_star_radius: u32,
+    pub min_star_system_radius: u32,
+    pub max_star_system_radius: u32,
+    pub min_num_planets: u32,
+    pub max_num_planets: u32,
+    pub min_planet_radius: u32,
+    pub max_planet_radius: u32,
+    /// Slowest and fastest orbits, before scaling by the slowdown factor.
+    pub min_planet_dq: u32,
+    pub max_planet_dq: u32,
+    /// Largest eccentricity of a planet orbit, between 0 and 1 exclusive.
+    pub max_eccentricity: f64,
+    pub max_num_moons: u32,
+    pub min_moon_radius: u32,
+    pub max_moon_radius: u32,
+    /// Percentage of planets that have moons.
+    pub moon_chance: u32,
+    /// Largest extra gap between the capture areas of neighbouring moon
+    /// orbits.
+    pub moon_orbit_spread: u32,
+    /// Size of the capture area of a planet, as a multiple of its radius.
+    pub planet_activate_range: u32,
+    /// Attempts at placing a body, and at placing all bodies of a system,
+    /// before giving up.
+    pub max_tries: u32,
+    pub slowdown_factor: f64,
+    pub packet_speed: f64,
+    pub packet_radius: u32,
+    /// Slowest rotation of a bound packet around its planet.
+    pub min_packet_dq: f64,
+    pub trail_length: u32,
+    pub min_payload_size: u32,
+    pub max_payload_size: u32,
+    /// Ticks the packet may spend away from the source before it expires.
+    pub packet_ttl: u32,
+    pub min_queue_capacity: u32,
+    pub max_queue_capacity: u32,
+    pub min_processing_delay: u32,
+    pub max_processing_delay: u32,
+    /// Payload units a planet transmits per tick, which adds to its
+    /// processing delay.
+    pub bandwidth: u32,
+    /// Percentage of planets that are only online during link windows.
+    pub link_window_chance: u32,
+    pub min_link_period: u32,
+    pub max_link_period: u32,
+    /// Smallest and largest part of a link period or orbit that a planet is
+    /// online, in percent.
+    pub min_link_online: u32,
+    pub max_link_online: u32,
+    /// Percentage of planets of each special kind. The rest are normal.
+    pub amplifier_chance: u32,
+    pub reflector_chance: u32,
+    pub reverse_spin_chance: u32,
+    pub sink_chance: u32,
+    pub charger_chance: u32,
+    /// Factor by which amplifiers speed up the packets they send on.
+    pub amplifier_boost: f64,
+    /// Lets the player reverse, speed up, slow down and hold the rotation
+    /// of a bound packet.
+    pub manual_spin: bool,
+    /// Slowest and fastest rotation the player can give a bound packet, and
+    /// the change in rotation of each speed up or slow down.
+    pub min_spin_dq: f64,
+    pub max_spin_dq: f64,
+    pub spin_step: f64,
+    /// Energy available in each round, or 0 for unlimited energy.
+    pub energy_budget: u32,
+    /// Energy spent for each unit of distance the packet flies.
+    pub energy_per_distance: f64,
+    /// Energy a charger restores when it captures the packet.
+    pub charger_energy: u32,
+    /// Length of a time attack game in ticks.
+    pub time_attack_ticks: u32,
+    /// Losses that end an endless game.
+    pub endless_lives: u32,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            mode: GameMode::Classic,
+            difficulty: Difficulty::Normal,
+            generator: GeneratorKind::Classic,
+            min_num_stars: 3,
+            max_num_stars: 5,
+            min_star_radius: 8,
+            max_star_radius: 12,
+            min_star_system_radius: 150,
+            max_star_system_radius: 300,
+            min_num_planets: 1,
+            max_num_planets: 6,
+            min_planet_radius: 3,
+            max_planet_radius: 8,
+            min_planet_dq: 1,
+            max_planet_dq: 8,
+            max_eccentricity: 0.3,
+            max_num_moons: 2,
+            min_moon_radius: 2,
+            max_moon_radius: 3,
+            moon_chance: 30,
+            moon_orbit_spread: 12,
+            planet_activate_range: 3,
+            max_tries: 10,
+            slowdown_factor: 0.2,
+            packet_speed: 13.0,
+            packet_radius: 7,
+            min_packet_dq: 1.0,
+            trail_length: 24,
+            min_payload_size: 1,
+            max_payload_size: 8,
+            packet_ttl: 1800,
+            min_queue_capacity: 4,
+            max_queue_capacity: 16,
+            min_processing_delay: 0,
+            max_processing_delay: 30,
+            bandwidth: 2,
+            link_window_chance: 30,
+            min_link_period: 120,
+            max_link_period: 360,
+            min_link_online: 30,
+            max_link_online: 70,
+            amplifier_chance: 10,
+            reflector_chance: 8,
+            reverse_spin_chance: 10,
+            sink_chance: 5,
+            charger_chance: 8,
+            amplifier_boost: 1.6,
+            manual_spin: false,
+            min_spin_dq: 0.5,
+            max_spin_dq: 6.0,
+            spin_step: 0.5,
+            energy_budget: 100,
+            energy_per_distance: 0.02,
+            charger_energy: 40,
+            time_attack_ticks: 3600,
+            endless_lives: 3,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl GameConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GameConfig {
+        GameConfig::default()
+    }
+}
+
+impl GameConfig {
+    /// Returns a copy with every range ordered and every value within the
+    /// bounds that generation relies on.
+    pub fn sanitized(&self) -> GameConfig {
+        let mut config = *self;
+        let order = |min: &mut u32, max: &mut u32| {
+            if *max < *min {
+                std::mem::swap(min, max);
+            }
+        };
+        order(&mut config.min_num_stars, &mut config.max_num_stars);
+        order(&mut config.min_star_radius, &mut config.max_star_radius);
+        order(
+            &mut config.min_star_system_radius,
+            &mut config.max_star_system_radius,
+        );
+        order(&mut config.min_num_planets, &mut config.max_num_planets);
+        order(&mut config.min_planet_radius, &mut config.max_planet_radius);
+        order(&mut config.min_planet_dq, &mut config.max_planet_dq);
+        order(&mut config.min_moon_radius, &mut config.max_moon_radius);
+        order(&mut config.min_payload_size, &mut config.max_payload_size);
+        order(
+            &mut config.min_queue_capacity,
+            &mut config.max_queue_capacity,
+        );
+        order(
+            &mut config.min_processing_delay,
+            &mut config.max_processing_delay,
+        );
+        order(&mut config.min_link_period, &mut config.max_link_period);
+        order(&mut config.min_link_online, &mut config.max_link_online);
+        config.min_num_stars = config.min_num_stars.max(1);
+        config.max_num_stars = config.max_num_stars.max(1);
+        config.max_tries = config.max_tries.max(1);
+        config.packet_ttl = config.packet_ttl.max(1);
+        config.bandwidth = config.bandwidth.max(1);
+        config.max_eccentricity = config.max_eccentricity.clamp(0.0, 0.9);
+        config.moon_chance = config.moon_chance.min(100);
+        config.link_window_chance = config.link_window_chance.min(100);
+        config.min_link_period = config.min_link_period.max(1);
+        config.max_link_online = config.max_link_online.min(100);
+        config.min_link_online = config.min_link_online.min(config.max_link_online);
+        // The chances of the special kinds share the same 100 percent
+        let mut remaining = 100;
+        for chance in [
+            &mut config.amplifier_chance,
+            &mut config.reflector_chance,
+            &mut config.reverse_spin_chance,
+            &mut config.sink_chance,
+            &mut config.charger_chance,
+        ] {
+            *chance = (*chance).min(remaining);
+            remaining -= *chance;
+        }
+        config.amplifier_boost = config.amplifier_boost.max(0.1);
+        config.min_spin_dq = config.min_spin_dq.max(0.0);
+        config.max_spin_dq = config.max_spin_dq.max(config.min_spin_dq);
+        config.spin_step = config.spin_step.max(0.0);
+        config.energy_per_distance = config.energy_per_distance.max(0.0);
+        config.time_attack_ticks = config.time_attack_ticks.max(1);
+        config.endless_lives = config.endless_lives.max(1);
+        config
+    }
+
+    /// Smallest star system that still has room for an orbit of the largest
+    /// planet.
+    pub(crate) fn min_relaxed_system_radius(&self) -> u32 {
+        self.max_star_radius + 2 * self.planet_activate_range * self.max_planet_radius
+    }
+
+    /// Smallest side of a world that reliably generates, which leaves room
+    /// around a star system of the smallest size so that generators that
+    /// place stars at random still find a spot.
+    pub(crate) fn min_world_size(&self) -> u32 {
+        self.min_relaxed_system_radius() * 5 / 2
+    }
+
+    /// Cell size of the spatial index of the capture areas of the planets.
+    pub(crate) fn capture_cell_size(&self) -> u32 {
+        2 * (self.max_planet_radius * self.planet_activate_range + self.packet_radius)
+    }
+}
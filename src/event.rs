@@ -0,0 +1,83 @@
+use crate::bodies::PlanetId;
+use crate::universe::LossReason;
+use wasm_bindgen::prelude::*;
+
+/// State change of the simulation, queued for the host to react to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The packet was sent on from a planet.
+    Released {
+        planet: PlanetId,
+    },
+    /// A planet captured the packet and routes it.
+    Captured {
+        planet: PlanetId,
+    },
+    /// A reflector bounced the packet.
+    Reflected {
+        planet: PlanetId,
+    },
+    /// The packet reached the destination for the first time in the round.
+    ReachedDestination {
+        planet: PlanetId,
+    },
+    /// The packet made it back to the source, completing the round.
+    ReturnedToSource {
+        planet: PlanetId,
+    },
+    Lost {
+        reason: LossReason,
+    },
+    /// A new round was generated, which took the given number of attempts.
+    RoundStarted {
+        attempts: u32,
+    },
+    /// The game ended with the given score.
+    GameOver {
+        score: u32,
+    },
+}
+
+/// Kind of an [`Event`], as passed to JS with its value.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Released,
+    Captured,
+    Reflected,
+    ReachedDestination,
+    ReturnedToSource,
+    Lost,
+    RoundStarted,
+    GameOver,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Released { .. } => EventKind::Released,
+            Event::Captured { .. } => EventKind::Captured,
+            Event::Reflected { .. } => EventKind::Reflected,
+            Event::ReachedDestination { .. } => EventKind::ReachedDestination,
+            Event::ReturnedToSource { .. } => EventKind::ReturnedToSource,
+            Event::Lost { .. } => EventKind::Lost,
+            Event::RoundStarted { .. } => EventKind::RoundStarted,
+            Event::GameOver { .. } => EventKind::GameOver,
+        }
+    }
+
+    /// Returns the data of the event as a number: the planet index, the
+    /// [`LossReason`], the generation attempts or the score.
+    pub fn value(&self) -> u32 {
+        match *self {
+            Event::Released { planet }
+            | Event::Captured { planet }
+            | Event::Reflected { planet }
+            | Event::ReachedDestination { planet }
+            | Event::ReturnedToSource { planet } => planet.index() as u32,
+            Event::Lost { reason } => reason as u32,
+            Event::RoundStarted { attempts } => attempts,
+            Event::GameOver { score } => score,
+        }
+    }
+}
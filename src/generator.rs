@@ -0,0 +1,294 @@
+use crate::bodies::StarId;
+use crate::universe::Universe;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+const POISSON_CANDIDATES: u32 = 20;
+const SPIRAL_ARMS: u32 = 2;
+const SPIRAL_STEP: f64 = 0.15;
+const MAX_CLUSTERS: u32 = 2;
+
+/// Procedural generation algorithm used to lay out each round.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeneratorKind {
+    Classic,
+    PoissonDisc,
+    Spiral,
+    Clustered,
+    Grid,
+}
+
+/// Constraint that could not be satisfied while generating a round.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GenerationError {
+    /// The world cannot fit a single star system of the smallest size with
+    /// room around it.
+    WorldTooSmall {
+        width: u32,
+        height: u32,
+        min_size: u32,
+    },
+    /// Fewer star systems than required could be placed without overlap.
+    StarPlacement { placed: usize, required: u32 },
+    /// The orbits around a star could not be placed without overlap.
+    PlanetPlacement { star: usize },
+    /// There are not enough planets for a distinct source and destination.
+    TooFewPlanets { planets: usize },
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerationError::WorldTooSmall {
+                width,
+                height,
+                min_size,
+            } => write!(
+                f,
+                "world of {}x{} is too small, both sides must be at least {}",
+                width, height, min_size
+            ),
+            GenerationError::StarPlacement { placed, required } => write!(
+                f,
+                "could only place {} of {} star systems",
+                placed, required
+            ),
+            GenerationError::PlanetPlacement { star } => {
+                write!(f, "could not place the planets around star {}", star)
+            }
+            GenerationError::TooFewPlanets { planets } => {
+                write!(f, "generated {} planets, at least 2 are needed", planets)
+            }
+        }
+    }
+}
+
+impl Error for GenerationError {}
+
+pub trait Generator {
+    /// Places the star systems of a round. On error the universe is reset
+    /// and generation is retried.
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError>;
+
+    /// Places the planets around the stars.
+    fn generate_planets(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        universe.generate_planet_positions()
+    }
+}
+
+fn check_star_count(universe: &Universe, required: u32) -> Result<(), GenerationError> {
+    if (universe.num_stars() as u32) < required {
+        return Err(GenerationError::StarPlacement {
+            placed: universe.num_stars(),
+            required,
+        });
+    }
+    Ok(())
+}
+
+pub fn get(kind: GeneratorKind) -> &'static dyn Generator {
+    match kind {
+        GeneratorKind::Classic => &Classic,
+        GeneratorKind::PoissonDisc => &PoissonDisc,
+        GeneratorKind::Spiral => &Spiral,
+        GeneratorKind::Clustered => &Clustered,
+        GeneratorKind::Grid => &Grid,
+    }
+}
+
+/// Rejection sampling of star systems at uniformly random positions.
+pub struct Classic;
+
+impl Generator for Classic {
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        let (min_stars, max_stars) = universe.star_count_range();
+        let (min_radius, max_radius) = universe.system_radius_range();
+        let num_stars = universe.rand(min_stars, max_stars);
+        let max_tries = universe.config().max_tries;
+        let mut outer_tries = 0;
+        loop {
+            outer_tries += 1;
+            let mut generation_done = true;
+            for _ in 0..num_stars {
+                let mut tries = 0;
+                loop {
+                    tries += 1;
+                    let x = universe.rand(0, universe.world_width());
+                    let y = universe.rand(0, universe.world_height());
+                    let sys_radius = universe.rand(min_radius, max_radius);
+                    if universe.place_star(x, y, sys_radius).is_some() {
+                        break;
+                    } else if tries == max_tries {
+                        generation_done = false;
+                        break;
+                    }
+                }
+                if !generation_done {
+                    break;
+                }
+            }
+            if generation_done {
+                return Ok(());
+            }
+            if outer_tries == max_tries {
+                return check_star_count(universe, num_stars);
+            }
+            universe.clear_stars();
+        }
+    }
+}
+
+/// Poisson-disc sampling, growing the layout outwards from a random star
+/// so that systems end up evenly spaced.
+pub struct PoissonDisc;
+
+impl Generator for PoissonDisc {
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        let (min_stars, max_stars) = universe.star_count_range();
+        let (min_radius, max_radius) = universe.system_radius_range();
+        let max_tries = universe.config().max_tries;
+        let mut tries = 0;
+        while universe.num_stars() == 0 && tries < max_tries * max_tries {
+            tries += 1;
+            let x = universe.rand(0, universe.world_width());
+            let y = universe.rand(0, universe.world_height());
+            universe.place_star(x, y, min_radius);
+        }
+        let mut active = vec![StarId::new(0)];
+        while let Some(&star) = active.last() {
+            if universe.num_stars() == 0 || universe.num_stars() as u32 == max_stars {
+                break;
+            }
+            let (sx, sy, sr) = universe.star_system(star);
+            let mut placed = false;
+            for _ in 0..POISSON_CANDIDATES {
+                let sys_radius = universe.rand(min_radius, max_radius);
+                let q = (universe.rand(0, 359) as f64).to_radians();
+                let distance =
+                    (sr + sys_radius) as f64 * (1.0 + universe.rand(0, 100) as f64 / 100.0);
+                let x = sx as f64 + distance * q.cos();
+                let y = sy as f64 + distance * q.sin();
+                if x < 0.0 || y < 0.0 {
+                    continue;
+                }
+                if let Some(star) = universe.place_star(x as u32, y as u32, sys_radius) {
+                    active.push(star);
+                    placed = true;
+                    break;
+                }
+            }
+            if !placed {
+                active.pop();
+            }
+        }
+        check_star_count(universe, min_stars)
+    }
+}
+
+/// Star systems along the arms of an Archimedean spiral around the center
+/// of the world.
+pub struct Spiral;
+
+impl Generator for Spiral {
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        let (min_stars, max_stars) = universe.star_count_range();
+        let (min_radius, max_radius) = universe.system_radius_range();
+        let num_stars = universe.rand(min_stars, max_stars);
+        let cx = universe.world_width() as f64 / 2.0;
+        let cy = universe.world_height() as f64 / 2.0;
+        let max_distance = cx.min(cy);
+        let offset = (universe.rand(0, 359) as f64).to_radians();
+        let spacing = min_radius as f64 * 2.0 / PI;
+        for arm in 0..SPIRAL_ARMS {
+            let arm_offset = offset + 2.0 * PI * arm as f64 / SPIRAL_ARMS as f64;
+            let mut q = 0.0;
+            loop {
+                let distance = spacing * q;
+                if distance > max_distance || universe.num_stars() as u32 == num_stars {
+                    break;
+                }
+                let sys_radius = universe.rand(min_radius, max_radius);
+                let x = cx + distance * (q + arm_offset).cos();
+                let y = cy + distance * (q + arm_offset).sin();
+                if x >= 0.0 && y >= 0.0 {
+                    universe.place_star(x as u32, y as u32, sys_radius);
+                }
+                q += SPIRAL_STEP;
+            }
+        }
+        check_star_count(universe, min_stars)
+    }
+}
+
+/// Tight star systems packed around a few cluster centers.
+pub struct Clustered;
+
+impl Generator for Clustered {
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        let (min_stars, max_stars) = universe.star_count_range();
+        let (min_radius, max_radius) = universe.system_radius_range();
+        let num_stars = universe.rand(min_stars, max_stars);
+        let num_clusters = universe.rand(1, MAX_CLUSTERS);
+        let spread = max_radius * 2;
+        let mut centers = Vec::new();
+        for _ in 0..num_clusters {
+            centers.push((
+                universe.rand(0, universe.world_width()),
+                universe.rand(0, universe.world_height()),
+            ));
+        }
+        let max_tries = universe.config().max_tries;
+        let mut tries = 0;
+        while (universe.num_stars() as u32) < num_stars && tries < max_tries * max_tries {
+            tries += 1;
+            let (cx, cy) = centers[universe.rand(0, num_clusters - 1) as usize];
+            // Sum of two uniform samples, to bias positions towards the center
+            let dx =
+                universe.rand(0, spread) as i64 + universe.rand(0, spread) as i64 - spread as i64;
+            let dy =
+                universe.rand(0, spread) as i64 + universe.rand(0, spread) as i64 - spread as i64;
+            let (x, y) = (cx as i64 + dx, cy as i64 + dy);
+            let sys_radius = universe.rand(min_radius, (min_radius + max_radius) / 2);
+            if x >= 0 && y >= 0 {
+                universe.place_star(x as u32, y as u32, sys_radius);
+            }
+        }
+        check_star_count(universe, num_stars)
+    }
+}
+
+/// Equally sized star systems on a regular grid, with some cells left
+/// empty, for puzzle-like layouts.
+pub struct Grid;
+
+impl Generator for Grid {
+    fn generate_stars(&self, universe: &mut Universe) -> Result<(), GenerationError> {
+        let (min_stars, max_stars) = universe.star_count_range();
+        let (sys_radius, _) = universe.system_radius_range();
+        let cell = sys_radius * 2;
+        let columns = universe.world_width() / cell;
+        let rows = universe.world_height() / cell;
+        let margin_x = (universe.world_width() - columns * cell) / 2;
+        let margin_y = (universe.world_height() - rows * cell) / 2;
+        let mut cells: Vec<(u32, u32)> = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                cells.push((column, row));
+            }
+        }
+        let num_stars = universe.rand(min_stars, max_stars).min(cells.len() as u32);
+        for _ in 0..num_stars {
+            let index = universe.rand(0, cells.len() as u32 - 1) as usize;
+            let (column, row) = cells.swap_remove(index);
+            universe.place_star(
+                margin_x + column * cell + sys_radius,
+                margin_y + row * cell + sys_radius,
+                sys_radius,
+            );
+        }
+        check_star_count(universe, min_stars)
+    }
+}
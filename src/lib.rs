@@ -3,5 +3,16 @@ extern crate wee_alloc;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+pub mod bodies;
+pub mod challenge;
+pub mod config;
+pub mod event;
+pub mod generator;
+pub mod mode;
 mod packet;
+mod random;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod raster;
+mod spatial;
+pub mod stats;
 pub mod universe;
@@ -0,0 +1,159 @@
+use crate::config::GameConfig;
+use wasm_bindgen::prelude::*;
+
+/// Share of the packet TTL lost with each level of the endless mode, in
+/// percent, and the smallest share that is left.
+const ENDLESS_TTL_DECAY: u32 = 5;
+const ENDLESS_MIN_TTL: u32 = 25;
+/// Increase of the largest payload, the sink chance and the link window
+/// chance with each level of the endless mode.
+const ENDLESS_PAYLOAD_STEP: u32 = 1;
+const ENDLESS_SINK_STEP: u32 = 2;
+const ENDLESS_LINK_WINDOW_STEP: u32 = 5;
+
+/// Way a game is played, which decides how it is scored and when it ends.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameMode {
+    /// Rounds are played forever.
+    Classic,
+    /// As many round trips as possible before the time runs out.
+    TimeAttack,
+    /// Rounds get harder with each round trip, until all lives are lost.
+    Endless,
+    /// Rounds are played forever, without scoring and without expiring or
+    /// running out of energy.
+    Zen,
+}
+
+/// Preset that makes every round easier or harder, on top of the rest of
+/// the rules.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// Scoring and end conditions of a game mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModeRules {
+    /// Whether round trips add to the score.
+    pub scores: bool,
+    /// Whether the packet can expire and the energy can run out.
+    pub fails: bool,
+    /// Whether the game ends after a number of ticks.
+    pub time_limited: bool,
+    /// Whether the game ends when all lives are lost.
+    pub limited_lives: bool,
+    /// Whether the rules get harder with each round trip.
+    pub escalates: bool,
+}
+
+const MODE_RULES: [ModeRules; 4] = [
+    // Classic
+    ModeRules {
+        scores: true,
+        fails: true,
+        time_limited: false,
+        limited_lives: false,
+        escalates: false,
+    },
+    // TimeAttack
+    ModeRules {
+        scores: true,
+        fails: true,
+        time_limited: true,
+        limited_lives: false,
+        escalates: false,
+    },
+    // Endless
+    ModeRules {
+        scores: true,
+        fails: true,
+        time_limited: false,
+        limited_lives: true,
+        escalates: true,
+    },
+    // Zen
+    ModeRules {
+        scores: false,
+        fails: false,
+        time_limited: false,
+        limited_lives: false,
+        escalates: false,
+    },
+];
+
+impl GameMode {
+    pub fn rules(self) -> &'static ModeRules {
+        &MODE_RULES[self as usize]
+    }
+}
+
+/// Summary of a game, available once it is over.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameResults {
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+    pub score: u32,
+    /// Round trips from the source to the destination and back.
+    pub deliveries: u32,
+    /// Times the packet was sent on from a planet.
+    pub shots: u32,
+    /// Times the packet was lost.
+    pub losses: u32,
+    /// Ticks the game lasted.
+    pub ticks: u32,
+    /// Shots that ended on a planet that routed the packet.
+    pub hits: u32,
+    /// Most round trips in a row without losing the packet.
+    pub best_streak: u32,
+    /// Highest difficulty reached, which only rises in the endless mode.
+    pub level: u32,
+}
+
+/// Returns the rules adjusted for the difficulty of the config: easy games
+/// give the packet more TTL and energy and have fewer sinks, hard games the
+/// opposite and larger payloads.
+pub(crate) fn with_difficulty(config: &GameConfig) -> GameConfig {
+    let mut config = *config;
+    let scale = |value: u32, percent: u32| (value as u64 * percent as u64 / 100) as u32;
+    match config.difficulty {
+        Difficulty::Easy => {
+            config.packet_ttl = scale(config.packet_ttl, 150);
+            config.energy_budget = scale(config.energy_budget, 150);
+            config.sink_chance /= 2;
+        }
+        Difficulty::Normal => {}
+        Difficulty::Hard => {
+            config.packet_ttl = scale(config.packet_ttl, 75);
+            config.energy_budget = scale(config.energy_budget, 75);
+            config.sink_chance = config.sink_chance.saturating_mul(2);
+            config.max_payload_size = config.max_payload_size.saturating_add(2);
+        }
+    }
+    config.sanitized()
+}
+
+/// Returns the rules for the given level of the endless mode: the TTL gets
+/// shorter, payloads get larger, and sinks and link windows more common.
+pub(crate) fn escalated(config: &GameConfig, level: u32) -> GameConfig {
+    let mut config = *config;
+    let ttl_share = 100u32
+        .saturating_sub(ENDLESS_TTL_DECAY.saturating_mul(level))
+        .max(ENDLESS_MIN_TTL);
+    config.packet_ttl = (config.packet_ttl as u64 * ttl_share as u64 / 100) as u32;
+    config.max_payload_size = config
+        .max_payload_size
+        .saturating_add(ENDLESS_PAYLOAD_STEP.saturating_mul(level));
+    config.sink_chance = config
+        .sink_chance
+        .saturating_add(ENDLESS_SINK_STEP.saturating_mul(level));
+    config.link_window_chance = config
+        .link_window_chance
+        .saturating_add(ENDLESS_LINK_WINDOW_STEP.saturating_mul(level));
+    config.sanitized()
+}
@@ -1,9 +1,10 @@
-use crate::universe::{MAX_PLANET_DQ, MIN_PACKET_DQ, SLOWDOWN_FACTOR};
+use crate::bodies::PlanetId;
+use crate::config::GameConfig;
 
 #[derive(Clone, Copy)]
 pub enum Packet {
     Bound {
-        planet: usize,
+        planet: PlanetId,
         q: f64,
         dq: f64,
         direction: bool,
@@ -13,7 +14,7 @@ pub enum Packet {
         y: f64,
         dx: f64,
         dy: f64,
-        last_planet: usize,
+        last_planet: Option<PlanetId>,
     },
 }
 
@@ -24,37 +25,44 @@ impl Packet {
             y: 0.0,
             dx: 0.0,
             dy: 0.0,
-            last_planet: 0,
+            last_planet: None,
         }
     }
 
-    pub fn set_bound(planet: usize, q: f64, planet_dq: f64, direction: bool) -> Packet {
+    pub fn set_bound(
+        planet: PlanetId,
+        q: f64,
+        planet_dq: f64,
+        direction: bool,
+        config: &GameConfig,
+    ) -> Packet {
         Packet::Bound {
             planet,
             q,
-            dq: (MAX_PLANET_DQ as f64 * SLOWDOWN_FACTOR - planet_dq + MIN_PACKET_DQ),
+            dq: (config.max_planet_dq as f64 * config.slowdown_factor - planet_dq
+                + config.min_packet_dq),
             direction,
         }
     }
 
-    pub fn set_free(x: f64, y: f64, dx: f64, dy: f64, last_planet: usize) -> Packet {
+    pub fn set_free(x: f64, y: f64, dx: f64, dy: f64, last_planet: PlanetId) -> Packet {
         Packet::Free {
             x,
             y,
             dx,
             dy,
-            last_planet,
+            last_planet: Some(last_planet),
         }
     }
 
-    pub fn get_last_planet(&self) -> usize {
+    pub fn get_last_planet(&self) -> Option<PlanetId> {
         match self {
             Self::Bound {
                 planet: _,
                 q: _,
                 dq: _,
                 direction: _,
-            } => 0,
+            } => None,
             Self::Free {
                 x: _,
                 y: _,
@@ -65,7 +73,61 @@ impl Packet {
         }
     }
 
-    pub fn tick(packet: Packet, width: u32, height: u32) -> (bool, Packet) {
+    pub fn get_bound_planet(&self) -> Option<PlanetId> {
+        match self {
+            Self::Bound {
+                planet,
+                q: _,
+                dq: _,
+                direction: _,
+            } => Some(*planet),
+            Self::Free {
+                x: _,
+                y: _,
+                dx: _,
+                dy: _,
+                last_planet: _,
+            } => None,
+        }
+    }
+
+    /// Replaces any reference to `old` with `new`. A bound packet cannot
+    /// lose its planet, so `new` is only `None` for a free packet.
+    pub fn replace_planet(&mut self, old: PlanetId, new: Option<PlanetId>) {
+        match self {
+            Self::Bound { planet, .. } => {
+                if let Some(new) = new {
+                    if *planet == old {
+                        *planet = new;
+                    }
+                }
+            }
+            Self::Free { last_planet, .. } => {
+                if *last_planet == Some(old) {
+                    *last_planet = new;
+                }
+            }
+        }
+    }
+
+    /// Reverses the rotation of a bound packet.
+    pub fn reverse_spin(&mut self) {
+        if let Self::Bound { direction, .. } = self {
+            *direction = !*direction;
+        }
+    }
+
+    /// Changes the rotation speed of a bound packet by `delta`, keeping it
+    /// between `min` and `max`.
+    pub fn change_spin(&mut self, delta: f64, min: f64, max: f64) {
+        if let Self::Bound { dq, .. } = self {
+            *dq = (*dq + delta).clamp(min, max);
+        }
+    }
+
+    /// Moves the packet by one tick. A bound packet keeps its aim while
+    /// `spin_held` is set.
+    pub fn tick(packet: Packet, width: u32, height: u32, spin_held: bool) -> (bool, Packet) {
         match packet {
             Packet::Bound {
                 planet,
@@ -74,7 +136,9 @@ impl Packet {
                 direction,
             } => {
                 let mut new_q;
-                if direction {
+                if spin_held {
+                    new_q = q;
+                } else if direction {
                     new_q = q + dq;
                 } else {
                     new_q = q + (360.0 - dq);
@@ -99,8 +163,8 @@ impl Packet {
                 dy,
                 last_planet,
             } => {
-                let new_x = x as f64 + dx;
-                let new_y = y as f64 + dy;
+                let new_x = x + dx;
+                let new_y = y + dy;
                 if new_x < 0.0 || new_x > width as f64 || new_y < 0.0 || new_y > height as f64 {
                     return (false, packet);
                 }
@@ -118,6 +182,15 @@ impl Packet {
         }
     }
 
+    /// Returns the distance a free packet moves in each tick along both
+    /// axes, or zero for a bound packet.
+    pub fn velocity(&self) -> (f64, f64) {
+        match self {
+            Self::Bound { .. } => (0.0, 0.0),
+            Self::Free { dx, dy, .. } => (*dx, *dy),
+        }
+    }
+
     pub fn is_bound(&self) -> bool {
         match &self {
             Packet::Bound {
@@ -0,0 +1,56 @@
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(module = "/util.js")]
+extern "C" {
+    fn gen_rand(start: u32, end: u32) -> u32;
+}
+
+/// Xorshift random number generator, so that a universe can be
+/// regenerated from its seed.
+#[derive(Clone)]
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Random {
+        // Xorshift gets stuck at zero
+        Random {
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+        }
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Returns a random number between start and end, both inclusive.
+    pub fn gen_rand(&mut self, start: u32, end: u32) -> u32 {
+        if end <= start {
+            return start;
+        }
+        let range = end as u64 - start as u64 + 1;
+        start + (self.next() as u64 % range) as u32
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn random_seed() -> u32 {
+    gen_rand(0, u32::MAX - 1)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn random_seed() -> u32 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() ^ time.as_secs() as u32)
+        .unwrap_or(0)
+}
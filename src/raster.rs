@@ -0,0 +1,231 @@
+use crate::universe::Universe;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+pub type Color = [u8; 4];
+
+const BACKGROUND: Color = [0x07, 0x02, 0x19, 0xff];
+const COLORS: [Color; 6] = [
+    [0xff, 0x00, 0x00, 0xff],
+    [0x00, 0xff, 0x00, 0xff],
+    [0xea, 0xea, 0x8a, 0xff],
+    [0xcb, 0xf1, 0xf2, 0xff],
+    [0xff, 0x88, 0x00, 0xff],
+    [0x44, 0x47, 0x5a, 0xff],
+];
+// Ring colors of the planet kinds, indexed by kind
+const KIND_COLORS: [Color; 6] = [
+    [0xcb, 0xf1, 0xf2, 0xff],
+    [0xff, 0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff, 0xff],
+    [0x00, 0xcc, 0xff, 0xff],
+    [0x8b, 0x00, 0xff, 0xff],
+    [0xaa, 0xff, 0x00, 0xff],
+];
+const OUTLINES: [Color; 2] = [[0xcb, 0xf1, 0xf2, 0x33], [0xea, 0xea, 0x8a, 0x1a]];
+// Capture areas and velocity vector of the debug overlay
+const DEBUG: [Color; 2] = [[0xff, 0xff, 0x00, 0x55], [0xff, 0xff, 0x00, 0xff]];
+const PATHS: [Color; 2] = [[0xff, 0x00, 0x00, 0x99], [0x00, 0xff, 0x00, 0x55]];
+
+/// RGBA image that frames are rasterized into.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels in row-major RGBA order.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn fill(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: Color) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let index = ((y as usize * self.width as usize) + x as usize) * 4;
+        let alpha = color[3] as u32;
+        for (old, new) in self.pixels[index..index + 3].iter_mut().zip(color.iter()) {
+            *old = ((*new as u32 * alpha + *old as u32 * (255 - alpha)) / 255) as u8;
+        }
+        self.pixels[index + 3] = 0xff;
+    }
+
+    pub fn fill_circle(&mut self, x: f64, y: f64, radius: f64, color: Color) {
+        let r = radius.ceil() as i64;
+        let (cx, cy) = (x as i64, y as i64);
+        for py in cy - r..=cy + r {
+            for px in cx - r..=cx + r {
+                let (dx, dy) = (px as f64 - x, py as f64 - y);
+                if dx * dx + dy * dy <= radius * radius {
+                    self.blend(px, py, color);
+                }
+            }
+        }
+    }
+
+    pub fn stroke_circle(&mut self, x: f64, y: f64, radius: f64, color: Color) {
+        self.stroke_ellipse(x, y, radius, radius, 0.0, color);
+    }
+
+    /// Strokes an ellipse with radii `rx` and `ry`, rotated clockwise by
+    /// `rotation` degrees around its center.
+    pub fn stroke_ellipse(
+        &mut self,
+        x: f64,
+        y: f64,
+        rx: f64,
+        ry: f64,
+        rotation: f64,
+        color: Color,
+    ) {
+        let (rx, ry) = (rx.max(0.5), ry.max(0.5));
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let r = rx.max(ry).ceil() as i64 + 1;
+        let (cx, cy) = (x as i64, y as i64);
+        for py in cy - r..=cy + r {
+            for px in cx - r..=cx + r {
+                let (dx, dy) = (px as f64 - x, py as f64 - y);
+                // Offset in the frame of the ellipse
+                let (u, v) = (dx * cos + dy * sin, dy * cos - dx * sin);
+                let distance = (u * u + v * v).sqrt();
+                let scaled = ((u / rx).powi(2) + (v / ry).powi(2)).sqrt();
+                // Distance to the ellipse along the ray from its center
+                if scaled > 0.0 && (distance - distance / scaled).abs() < 0.5 {
+                    self.blend(px, py, color);
+                }
+            }
+        }
+    }
+
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, color: Color) {
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0);
+        for step in 0..=steps as i64 {
+            let t = step as f64 / steps;
+            self.blend(
+                (x1 + (x2 - x1) * t).round() as i64,
+                (y1 + (y2 - y1) * t).round() as i64,
+                color,
+            );
+        }
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+/// Rasterizes the current state of the universe the same way index.html
+/// draws a frame. The status text is not drawn.
+pub fn render(universe: &Universe) -> Canvas {
+    let mut canvas = Canvas::new(universe.viewport_width(), universe.viewport_height());
+    canvas.fill(BACKGROUND);
+    let mut data = Vec::new();
+    universe.build_frame(&mut data);
+    let data: Vec<f64> = data.into_iter().map(|value| value as i32 as f64).collect();
+    let mut index = 7;
+    let n = data[index] as usize;
+    index += 1;
+    for _ in 0..n {
+        canvas.stroke_ellipse(
+            data[index],
+            data[index + 1],
+            data[index + 2],
+            data[index + 3],
+            data[index + 4],
+            OUTLINES[0],
+        );
+        index += 5;
+    }
+    let n = data[index] as usize;
+    index += 1;
+    for _ in 0..n {
+        canvas.stroke_circle(data[index], data[index + 1], data[index + 2], OUTLINES[1]);
+        index += 3;
+    }
+    for color in PATHS.iter() {
+        let n = data[index] as usize;
+        index += 1;
+        for i in 1..n {
+            let p = index + i * 2;
+            canvas.line(data[p - 2], data[p - 1], data[p], data[p + 1], *color);
+        }
+        index += n * 2;
+    }
+    if data[index] > 0.0 {
+        canvas.line(
+            data[index + 1],
+            data[index + 2],
+            data[index + 3],
+            data[index + 4],
+            COLORS[0],
+        );
+        index += 5;
+    } else {
+        index += 1;
+    }
+    for color in COLORS.iter() {
+        let n = data[index] as usize;
+        index += 1;
+        for _ in 0..n {
+            canvas.fill_circle(data[index], data[index + 1], data[index + 2], *color);
+            index += 3;
+        }
+    }
+    let n = data[index] as usize;
+    index += 1;
+    for _ in 0..n {
+        let color = KIND_COLORS[data[index + 3] as usize % KIND_COLORS.len()];
+        canvas.stroke_circle(data[index], data[index + 1], data[index + 2] * 2.0, color);
+        index += 4;
+    }
+    if data[index] > 0.0 {
+        // The attempts and the last planet are only shown as text
+        index += 3;
+        canvas.line(
+            data[index],
+            data[index + 1],
+            data[index + 2],
+            data[index + 3],
+            DEBUG[1],
+        );
+        index += 4;
+        let n = data[index] as usize;
+        index += 1;
+        for _ in 0..n {
+            canvas.stroke_circle(data[index], data[index + 1], data[index + 2], DEBUG[0]);
+            index += 4;
+        }
+    }
+    canvas
+}
@@ -0,0 +1,86 @@
+/// Uniform grid over the world for finding circles that may overlap a
+/// given circle, without testing against every circle in the world.
+pub struct SpatialGrid {
+    cell_size: f64,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(width: u32, height: u32, cell_size: u32) -> SpatialGrid {
+        let cell_size = cell_size.max(1);
+        let columns = (width / cell_size + 1) as usize;
+        let rows = (height / cell_size + 1) as usize;
+        SpatialGrid {
+            cell_size: cell_size as f64,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+    }
+
+    /// Returns the range of cells covered by a circle, clamped to the grid.
+    fn cell_range(&self, x: f64, y: f64, radius: f64) -> (usize, usize, usize, usize) {
+        let cell = |value: f64, count: usize| {
+            ((value / self.cell_size).floor().max(0.0) as usize).min(count - 1)
+        };
+        (
+            cell(x - radius, self.columns),
+            cell(y - radius, self.rows),
+            cell(x + radius, self.columns),
+            cell(y + radius, self.rows),
+        )
+    }
+
+    /// Adds an item occupying the given circle.
+    pub fn insert(&mut self, item: usize, x: f64, y: f64, radius: f64) {
+        let (left, top, right, bottom) = self.cell_range(x, y, radius);
+        for row in top..=bottom {
+            for column in left..=right {
+                self.cells[row * self.columns + column].push(item);
+            }
+        }
+    }
+
+    /// Adds an item occupying the ring between the `inner` and `outer`
+    /// radius around the given center, skipping the cells that lie entirely
+    /// within the inner radius.
+    pub fn insert_annulus(&mut self, item: usize, x: f64, y: f64, inner: f64, outer: f64) {
+        let (left, top, right, bottom) = self.cell_range(x, y, outer);
+        for row in top..=bottom {
+            for column in left..=right {
+                // Nearest and farthest distance from the center to the cell
+                let (x0, y0) = (column as f64 * self.cell_size, row as f64 * self.cell_size);
+                let (x1, y1) = (x0 + self.cell_size, y0 + self.cell_size);
+                let near_x = (x0 - x).max(x - x1).max(0.0);
+                let near_y = (y0 - y).max(y - y1).max(0.0);
+                let far_x = (x - x0).abs().max((x1 - x).abs());
+                let far_y = (y - y0).abs().max((y1 - y).abs());
+                if near_x.hypot(near_y) <= outer && far_x.hypot(far_y) >= inner {
+                    self.cells[row * self.columns + column].push(item);
+                }
+            }
+        }
+    }
+
+    /// Collects the items that share a cell with the given circle into
+    /// `candidates`, in increasing order and without duplicates.
+    pub fn query(&self, x: f64, y: f64, radius: f64, candidates: &mut Vec<usize>) {
+        candidates.clear();
+        let (left, top, right, bottom) = self.cell_range(x, y, radius);
+        for row in top..=bottom {
+            for column in left..=right {
+                candidates.extend_from_slice(&self.cells[row * self.columns + column]);
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+    }
+}
@@ -0,0 +1,296 @@
+use crate::mode::{Difficulty, GameMode, GameResults};
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(module = "/util.js")]
+extern "C" {
+    fn load_item(key: &str) -> Option<String>;
+    fn save_item(key: &str, value: &str) -> bool;
+}
+
+/// Key the statistics are stored under.
+pub const STATS_KEY: &str = "ipi-game-stats";
+const STATS_HEADER: &str = "ipi-game-stats 1";
+const MAX_TOP_SCORES: usize = 10;
+const NUM_MODES: usize = 4;
+const NUM_DIFFICULTIES: usize = 3;
+
+/// Persistent key-value store that statistics are saved to.
+pub trait StatsStorage {
+    /// Returns the value stored under `key`, or `None` if there is none.
+    fn load(&self, key: &str) -> io::Result<Option<String>>;
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()>;
+}
+
+/// Storage in the localStorage of the browser.
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage;
+
+#[cfg(target_arch = "wasm32")]
+impl StatsStorage for LocalStorage {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(load_item(key))
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()> {
+        if save_item(key, value) {
+            Ok(())
+        } else {
+            Err(io::Error::other("localStorage is not available"))
+        }
+    }
+}
+
+/// Storage in a directory, with one file for each key.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileStorage {
+        FileStorage { dir: dir.into() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StatsStorage for FileStorage {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.dir.join(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(key), value)
+    }
+}
+
+/// Totals of all games played in a mode.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModeStats {
+    pub games: u32,
+    pub deliveries: u32,
+    pub shots: u32,
+    pub hits: u32,
+    pub longest_streak: u32,
+    /// Best scores for each difficulty, highest first.
+    top_scores: [Vec<u32>; NUM_DIFFICULTIES],
+}
+
+impl ModeStats {
+    /// Returns the share of shots that ended on a planet, between 0 and 1.
+    pub fn accuracy(&self) -> f64 {
+        if self.shots == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.shots as f64
+        }
+    }
+
+    pub fn top_scores(&self, difficulty: Difficulty) -> &[u32] {
+        &self.top_scores[difficulty as usize]
+    }
+}
+
+/// High scores and totals of the games played, for each mode.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    modes: [ModeStats; NUM_MODES],
+}
+
+#[wasm_bindgen]
+impl Stats {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Loads the statistics from the localStorage of the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load_local() -> Stats {
+        Stats::load(&LocalStorage)
+    }
+
+    /// Saves the statistics to the localStorage of the browser, and returns
+    /// whether that succeeded.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save_local(&self) -> bool {
+        self.save(&mut LocalStorage).is_ok()
+    }
+
+    /// Adds a finished game. Returns the rank of its score among the top
+    /// scores of its mode and difficulty, counted from 0, if it made it.
+    pub fn record_game(&mut self, results: &GameResults) -> Option<u32> {
+        let stats = &mut self.modes[results.mode as usize];
+        stats.games += 1;
+        stats.deliveries += results.deliveries;
+        stats.shots += results.shots;
+        stats.hits += results.hits;
+        stats.longest_streak = stats.longest_streak.max(results.best_streak);
+        if !results.mode.rules().scores {
+            return None;
+        }
+        let scores = &mut stats.top_scores[results.difficulty as usize];
+        let rank = scores
+            .iter()
+            .position(|&score| results.score > score)
+            .unwrap_or(scores.len());
+        if rank >= MAX_TOP_SCORES {
+            return None;
+        }
+        scores.insert(rank, results.score);
+        scores.truncate(MAX_TOP_SCORES);
+        Some(rank as u32)
+    }
+
+    pub fn games(&self, mode: GameMode) -> u32 {
+        self.modes[mode as usize].games
+    }
+
+    pub fn deliveries(&self, mode: GameMode) -> u32 {
+        self.modes[mode as usize].deliveries
+    }
+
+    pub fn accuracy(&self, mode: GameMode) -> f64 {
+        self.modes[mode as usize].accuracy()
+    }
+
+    pub fn longest_streak(&self, mode: GameMode) -> u32 {
+        self.modes[mode as usize].longest_streak
+    }
+
+    /// Returns the best scores of a mode and difficulty, highest first.
+    pub fn top_scores(&self, mode: GameMode, difficulty: Difficulty) -> Vec<u32> {
+        self.modes[mode as usize].top_scores(difficulty).to_vec()
+    }
+}
+
+impl Stats {
+    pub fn mode(&self, mode: GameMode) -> &ModeStats {
+        &self.modes[mode as usize]
+    }
+
+    /// Loads the statistics from `storage`. Missing or unreadable
+    /// statistics start over from zero.
+    pub fn load(storage: &dyn StatsStorage) -> Stats {
+        match storage.load(STATS_KEY) {
+            Ok(Some(text)) => Stats::parse(&text).unwrap_or_default(),
+            _ => Stats::default(),
+        }
+    }
+
+    pub fn save(&self, storage: &mut dyn StatsStorage) -> io::Result<()> {
+        storage.save(STATS_KEY, &self.to_text())
+    }
+
+    /// Writes the statistics as lines of the form
+    /// `mode <mode> <games> <deliveries> <shots> <hits> <longest streak>` and
+    /// `top <mode> <difficulty> <scores...>`.
+    fn to_text(&self) -> String {
+        let mut text = String::from(STATS_HEADER);
+        text.push('\n');
+        for (mode, stats) in self.modes.iter().enumerate() {
+            text.push_str(&format!(
+                "mode {} {} {} {} {} {}\n",
+                mode, stats.games, stats.deliveries, stats.shots, stats.hits, stats.longest_streak
+            ));
+            for (difficulty, scores) in stats.top_scores.iter().enumerate() {
+                if scores.is_empty() {
+                    continue;
+                }
+                text.push_str(&format!("top {} {}", mode, difficulty));
+                for score in scores {
+                    text.push_str(&format!(" {}", score));
+                }
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Option<Stats> {
+        let mut lines = text.lines();
+        if lines.next()? != STATS_HEADER {
+            return None;
+        }
+        let mut stats = Stats::default();
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let kind = fields.next()?;
+            let numbers = fields
+                .map(|field| field.parse::<u32>().ok())
+                .collect::<Option<Vec<u32>>>()?;
+            let mode = stats.modes.get_mut(*numbers.first()? as usize)?;
+            match (kind, &numbers[1..]) {
+                ("mode", &[games, deliveries, shots, hits, longest_streak]) => {
+                    mode.games = games;
+                    mode.deliveries = deliveries;
+                    mode.shots = shots;
+                    mode.hits = hits;
+                    mode.longest_streak = longest_streak;
+                }
+                ("top", [difficulty, scores @ ..]) => {
+                    let top_scores = mode.top_scores.get_mut(*difficulty as usize)?;
+                    *top_scores = scores.iter().copied().take(MAX_TOP_SCORES).collect();
+                }
+                _ => return None,
+            }
+        }
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(mode: GameMode, difficulty: Difficulty, score: u32) -> GameResults {
+        GameResults {
+            mode,
+            difficulty,
+            score,
+            deliveries: score,
+            shots: score * 3 + 1,
+            losses: 2,
+            ticks: 3600,
+            hits: score * 2,
+            best_streak: score / 2,
+            level: 0,
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut stats = Stats::new();
+        for score in 0..12 {
+            stats.record_game(&results(GameMode::Classic, Difficulty::Normal, score));
+        }
+        stats.record_game(&results(GameMode::TimeAttack, Difficulty::Hard, 9));
+        stats.record_game(&results(GameMode::Endless, Difficulty::Easy, 4));
+        stats.record_game(&results(GameMode::Zen, Difficulty::Normal, 5));
+
+        let parsed = Stats::parse(&stats.to_text()).unwrap();
+        assert_eq!(parsed, stats);
+        assert_eq!(
+            parsed.top_scores(GameMode::Classic, Difficulty::Normal),
+            (2..12).rev().collect::<Vec<u32>>()
+        );
+        assert_eq!(parsed.games(GameMode::Zen), 1);
+        assert!(parsed
+            .top_scores(GameMode::Zen, Difficulty::Normal)
+            .is_empty());
+        assert_eq!(Stats::parse(&Stats::new().to_text()), Some(Stats::new()));
+    }
+}
@@ -1,287 +1,752 @@
+use crate::bodies::{Bodies, LinkWindow, PlanetId, PlanetKind, StarId};
+use crate::challenge::{self, Input, ResultCode, CHALLENGE_HEIGHT, CHALLENGE_WIDTH};
+use crate::config::GameConfig;
+use crate::event::Event;
+use crate::generator::{self, GenerationError, GeneratorKind};
+use crate::mode::{self, GameResults};
 use crate::packet::Packet;
+use crate::random::{random_seed, Random};
+use crate::spatial::SpatialGrid;
+use std::collections::VecDeque;
+use std::fmt::Write;
 use wasm_bindgen::prelude::*;
 
-const MIN_STAR_RADIUS: u32 = 8;
-const MAX_STAR_RADIUS: u32 = 12;
-const MIN_PLANET_RADIUS: u32 = 3;
-const MAX_PLANET_RADIUS: u32 = 8;
-const MIN_STAR_SYSTEM_RADIUS: u32 = 150;
-const MAX_STAR_SYSTEM_RADIUS: u32 = 300;
-const MIN_NUM_PLANETS: u32 = 1;
-const MAX_NUM_PLANETS: u32 = 6;
-const MIN_NUM_STARS: u32 = 3;
-const MAX_NUM_STARS: u32 = 5;
-const MIN_PLANET_DQ: u32 = 1;
-pub const MAX_PLANET_DQ: u32 = 8;
-const PLANET_ACTIVATE_RANGE: u32 = 3;
-const MAX_TRIES: u32 = 10;
-pub const SLOWDOWN_FACTOR: f64 = 0.2;
-const PACKET_SPEED: f64 = 13.0;
-const PACKET_RADIUS: u32 = 7;
-pub const MIN_PACKET_DQ: f64 = 1.0;
-
-#[wasm_bindgen(module = "/util.js")]
-extern "C" {
-    fn gen_rand(start: u32, end: u32) -> u32;
+const MAX_GENERATION_ATTEMPTS: u32 = 100;
+const RELAX_EVERY_ATTEMPTS: u32 = 5;
+/// Fewest planets per star system and shortest capture range that the
+/// planet rules are relaxed to.
+const MIN_RELAXED_PLANETS: u32 = 2;
+const MIN_RELAXED_ACTIVATE_RANGE: u32 = 2;
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 4.0;
+const CAMERA_FOLLOW_RATE: f64 = 0.1;
+const MIN_TIME_SCALE: f64 = 0.05;
+const MAX_TIME_SCALE: f64 = 4.0;
+/// Events kept for the host before the oldest are dropped.
+const MAX_QUEUED_EVENTS: usize = 256;
+/// Ticks of movement shown by the velocity vector of the debug overlay.
+const DEBUG_VELOCITY_TICKS: f64 = 8.0;
+
+/// Way the packet was lost, after which it restarts from the source, or a
+/// new round starts if it ran out of energy.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LossReason {
+    /// The packet flew out of the world.
+    LeftWindow,
+    /// The packet struck a star.
+    Absorbed,
+    /// The TTL of the packet ran out.
+    Expired,
+    /// The packet reached a planet whose queue is too small for it.
+    Dropped,
+    /// The packet was captured by a sink.
+    Swallowed,
+    /// The energy of the round ran out while the packet was flying.
+    OutOfEnergy,
 }
 
 #[wasm_bindgen]
 pub struct Universe {
+    rng: Random,
+    /// Rules as set by the player, and the rules of the current round,
+    /// which differ as the endless mode escalates.
+    base_config: GameConfig,
+    config: GameConfig,
+    generation_attempts: u32,
+    min_system_radius: u32,
+    max_system_radius: u32,
+    min_num_stars: u32,
+    max_num_stars: u32,
     width: u32,
     height: u32,
+    view_width: u32,
+    view_height: u32,
+    viewport_width: u32,
+    viewport_height: u32,
+    view_scale: f64,
+    camera_x: f64,
+    camera_y: f64,
+    zoom: f64,
+    follow_packet: bool,
     score: u32,
+    deliveries: u32,
+    shots: u32,
+    losses: u32,
+    hits: u32,
+    streak: u32,
+    best_streak: u32,
+    game_ticks: u32,
+    game_over: bool,
+    paused: bool,
+    time_scale: f64,
+    /// Fraction of a tick that the time scale has accumulated towards the
+    /// next one.
+    pending_time: f64,
+    /// Seed of the challenge this universe plays, whose games all start
+    /// from the same universe.
+    challenge_seed: Option<u32>,
+    inputs: Vec<(u32, Input)>,
+    events: VecDeque<Event>,
     packet: Packet,
-    packet_source: usize,
-    packet_destination: usize,
+    packet_source: PlanetId,
+    packet_destination: PlanetId,
     packet_reached_destination: bool,
-    star_x: Vec<u32>,
-    star_y: Vec<u32>,
-    star_radius: Vec<u32>,
-    star_system_radius: Vec<u32>,
-    planet_radius: Vec<u32>,
-    planet_star: Vec<u8>,
-    planet_distance: Vec<u32>,
-    planet_q: Vec<f64>,
-    planet_dq: Vec<f64>,
-    planet_direction: Vec<bool>,
+    packet_size: u32,
+    packet_ttl: u32,
+    packet_hold: u32,
+    energy: f64,
+    last_loss: Option<LossReason>,
+    spin_held: bool,
+    time: u32,
+    bodies: Bodies,
+    star_grid: SpatialGrid,
+    /// Index of the areas the planets can capture the packet in over their
+    /// whole orbits, which only changes when the planets do.
+    planet_grid: SpatialGrid,
+    planets_indexed: bool,
+    candidates: Vec<usize>,
+    packet_trail: VecDeque<(f64, f64)>,
+    route: Vec<(PlanetId, f64, f64)>,
+    last_route: Vec<(PlanetId, f64, f64)>,
+    show_orbits: bool,
+    show_system_boundaries: bool,
+    debug: bool,
+    frame: Vec<u32>,
 }
 
 #[wasm_bindgen]
 impl Universe {
     pub fn new(width: u32, height: u32) -> Universe {
-        let mut universe = Universe {
+        Universe::with_seed(width, height, random_seed())
+    }
+
+    pub fn with_seed(width: u32, height: u32, seed: u32) -> Universe {
+        Universe::with_world(width, height, width, height, seed)
+    }
+
+    /// Creates a universe spanning a world of `world_width` x `world_height`,
+    /// of which a `width` x `height` area is visible at zoom 1.
+    pub fn with_world(
+        width: u32,
+        height: u32,
+        world_width: u32,
+        world_height: u32,
+        seed: u32,
+    ) -> Universe {
+        Universe::with_config(
             width,
             height,
-            score: 0,
-            packet: Packet::new(),
-            packet_source: 0,
-            packet_destination: 0,
-            packet_reached_destination: false,
-            star_x: Vec::new(),
-            star_y: Vec::new(),
-            star_radius: Vec::new(),
-            star_system_radius: Vec::new(),
-            planet_radius: Vec::new(),
-            planet_star: Vec::new(),
-            planet_distance: Vec::new(),
-            planet_q: Vec::new(),
-            planet_dq: Vec::new(),
-            planet_direction: Vec::new(),
+            world_width,
+            world_height,
+            seed,
+            GameConfig::default(),
+        )
+    }
+
+    pub fn with_generator(
+        width: u32,
+        height: u32,
+        seed: u32,
+        generator: GeneratorKind,
+    ) -> Universe {
+        let config = GameConfig {
+            generator,
+            ..GameConfig::default()
         };
-        universe.generate();
-        universe
+        Universe::with_config(width, height, width, height, seed, config)
     }
 
-    fn generate(&mut self) {
-        loop {
-            if !self.generate_stars() || !self.generate_planet_positions() {
-                self.reset();
-            } else {
+    /// Creates a universe like [`Universe::with_world`] that plays by the
+    /// rules in `config`.
+    pub fn with_config(
+        width: u32,
+        height: u32,
+        world_width: u32,
+        world_height: u32,
+        seed: u32,
+        config: GameConfig,
+    ) -> Universe {
+        Universe::try_create(width, height, world_width, world_height, seed, config)
+            .unwrap_or_else(|err| throw_generation_error(err))
+    }
+
+    /// Creates the universe of a challenge from its seed. Challenges have a
+    /// fixed world size, which is fitted into the viewport with
+    /// [`Universe::resize`].
+    pub fn challenge(seed: u32) -> Universe {
+        Universe::try_create_challenge(seed).unwrap_or_else(|err| throw_generation_error(err))
+    }
+
+    /// Creates the universe of the challenge of a day, counted in days since
+    /// the Unix epoch, which is the same for every player.
+    pub fn daily_challenge(day: u32) -> Universe {
+        Universe::challenge(challenge::daily_seed(day))
+    }
+
+    /// Returns the code to share the result of a finished challenge.
+    pub fn result_code(&self) -> Option<String> {
+        let seed = self.challenge_seed?;
+        let results = self.results()?;
+        Some(
+            ResultCode {
+                seed,
+                score: results.score,
+                shots: results.shots,
+            }
+            .encode(),
+        )
+    }
+
+    /// Returns the inputs of the current game as (tick, input) pairs, to
+    /// verify a result code with [`challenge::verify_result`].
+    pub fn replay(&self) -> Vec<u32> {
+        let mut replay = Vec::with_capacity(self.inputs.len() * 2);
+        for (tick, input) in self.inputs.iter() {
+            replay.push(*tick);
+            replay.push(input.code());
+        }
+        replay
+    }
+
+    pub fn config(&self) -> GameConfig {
+        self.base_config
+    }
+
+    /// Changes the rules of the game. The packet and the trail follow the
+    /// new rules right away, while the layout only changes with the next
+    /// round.
+    ///
+    /// Throws if the world is too small for the new rules, which leaves the
+    /// previous rules in place.
+    pub fn set_config(&mut self, config: GameConfig) {
+        if let Err(err) = self.try_set_config(config) {
+            throw_generation_error(err);
+        }
+    }
+
+    /// Scales the star system size and the number of stars down to what
+    /// fits in the world, for small screens.
+    fn relax_constraints(&mut self) -> Result<(), GenerationError> {
+        let config = &self.config;
+        let half_size = self.width.min(self.height) / 2;
+        let min_relaxed = config.min_relaxed_system_radius();
+        let min_size = config.min_world_size();
+        if self.width.min(self.height) < min_size {
+            return Err(GenerationError::WorldTooSmall {
+                width: self.width,
+                height: self.height,
+                min_size,
+            });
+        }
+        self.max_system_radius = config
+            .max_star_system_radius
+            .min(half_size)
+            .max(min_relaxed);
+        self.min_system_radius = config
+            .min_star_system_radius
+            .min(
+                self.max_system_radius * config.min_star_system_radius
+                    / config.max_star_system_radius.max(1),
+            )
+            .max(min_relaxed);
+        let cell = self.min_system_radius * 2;
+        let capacity = ((self.width / cell) * (self.height / cell)).max(1);
+        self.max_num_stars = config.max_num_stars.min(capacity);
+        self.min_num_stars = config.min_num_stars.min(self.max_num_stars);
+        self.star_grid = SpatialGrid::new(self.width, self.height, self.max_system_radius * 2);
+        // The stars of the current round must stay in the new index
+        self.index_stars();
+        Ok(())
+    }
+
+    /// Generates a new round, giving up after a fixed number of attempts.
+    /// The rules are relaxed every few failed attempts, and the error of
+    /// the last attempt is returned if all of them fail.
+    fn generate(&mut self) -> Result<(), GenerationError> {
+        let generator = generator::get(self.config.generator);
+        let num_stars = (self.min_num_stars, self.max_num_stars);
+        let max_system_radius = self.max_system_radius;
+        let mut result = Ok(());
+        self.generation_attempts = 0;
+        while self.generation_attempts < MAX_GENERATION_ATTEMPTS {
+            self.generation_attempts += 1;
+            result = generator
+                .generate_stars(self)
+                .and_then(|_| generator.generate_planets(self))
+                .and_then(|_| self.check_planets());
+            if result.is_ok() {
                 break;
             }
+            self.reset();
+            if self
+                .generation_attempts
+                .is_multiple_of(RELAX_EVERY_ATTEMPTS)
+            {
+                if let Err(err) = &result {
+                    self.relax_generation(err);
+                }
+            }
         }
+        self.min_num_stars = num_stars.0;
+        self.max_num_stars = num_stars.1;
+        self.max_system_radius = max_system_radius;
+        result?;
         self.generate_planet_angles();
+        self.generate_routers();
         self.generate_packet();
+        self.generate_link_windows();
+        self.generate_kinds();
+        Ok(())
+    }
+
+    /// Relaxes the rule that made an attempt fail. Fewer and then smaller
+    /// star systems are placed if they did not fit. If the planets did not
+    /// fit, there are fewer moons and planets, then smaller planets with
+    /// shorter capture ranges. The star systems are restored after
+    /// generation, while the planet rules stay relaxed for the rest of the
+    /// round.
+    fn relax_generation(&mut self, err: &GenerationError) {
+        let config = &mut self.config;
+        match err {
+            GenerationError::WorldTooSmall { .. } | GenerationError::StarPlacement { .. } => {
+                if self.max_num_stars > self.min_num_stars {
+                    self.max_num_stars -= 1;
+                } else if self.min_num_stars > 1 {
+                    self.min_num_stars -= 1;
+                    self.max_num_stars = self.min_num_stars;
+                } else {
+                    self.max_system_radius = (self.min_system_radius + self.max_system_radius) / 2;
+                }
+            }
+            GenerationError::PlanetPlacement { .. } => {
+                if config.max_num_moons > 0 {
+                    config.max_num_moons -= 1;
+                } else if config.max_num_planets > MIN_RELAXED_PLANETS {
+                    config.max_num_planets -= 1;
+                    config.min_num_planets = config.min_num_planets.min(config.max_num_planets);
+                } else if config.min_num_planets > 1 && self.min_num_stars > 1 {
+                    // Other star systems provide the second planet
+                    config.min_num_planets -= 1;
+                } else {
+                    shrink_planets(config);
+                }
+            }
+            // A single star system must hold both the source and the
+            // destination
+            GenerationError::TooFewPlanets { .. } => {
+                if config.min_num_planets < MIN_RELAXED_PLANETS
+                    && config.max_num_planets >= MIN_RELAXED_PLANETS
+                {
+                    config.min_num_planets = MIN_RELAXED_PLANETS;
+                } else {
+                    shrink_planets(config);
+                }
+            }
+        }
+    }
+
+    fn check_planets(&self) -> Result<(), GenerationError> {
+        if self.num_planets() < 2 {
+            return Err(GenerationError::TooFewPlanets {
+                planets: self.num_planets(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the rules for the current round, which differ from the rules
+    /// set by the player by the difficulty and in modes that escalate.
+    fn round_config(&self) -> GameConfig {
+        let config = mode::with_difficulty(&self.base_config);
+        if config.mode.rules().escalates {
+            mode::escalated(&config, self.level())
+        } else {
+            config
+        }
+    }
+
+    /// Returns the difficulty of the current round.
+    pub fn level(&self) -> u32 {
+        if self.base_config.mode.rules().escalates {
+            self.deliveries
+        } else {
+            0
+        }
+    }
+
+    /// Returns the ticks left in a time attack game.
+    pub fn ticks_left(&self) -> Option<u32> {
+        if self.config.mode.rules().time_limited {
+            Some(
+                self.config
+                    .time_attack_ticks
+                    .saturating_sub(self.game_ticks),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Returns the losses left before an endless game ends.
+    pub fn lives_left(&self) -> Option<u32> {
+        if self.config.mode.rules().limited_lives {
+            Some(self.config.endless_lives.saturating_sub(self.losses))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Ends the game, as when the player stops playing in a mode without an
+    /// end condition.
+    pub fn end_game(&mut self) {
+        self.record_input(Input::EndGame);
+        self.finish_game();
+    }
+
+    fn finish_game(&mut self) {
+        if !self.game_over {
+            self.game_over = true;
+            self.push_event(Event::GameOver { score: self.score });
+        }
+    }
+
+    /// Returns the events since the last call as (kind, value) pairs, with
+    /// the kind numbered as in [`EventKind`](crate::event::EventKind) and
+    /// the value as in [`Event::value`], and clears the queue.
+    pub fn drain_events(&mut self) -> Vec<u32> {
+        let mut data = Vec::with_capacity(self.events.len() * 2);
+        for event in self.events.drain(..) {
+            data.push(event.kind() as u32);
+            data.push(event.value());
+        }
+        data
+    }
+
+    fn push_event(&mut self, event: Event) {
+        if self.events.len() == MAX_QUEUED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Returns the summary of the game once it is over.
+    pub fn results(&self) -> Option<GameResults> {
+        if !self.game_over {
+            return None;
+        }
+        Some(GameResults {
+            mode: self.config.mode,
+            score: self.score,
+            deliveries: self.deliveries,
+            shots: self.shots,
+            losses: self.losses,
+            difficulty: self.config.difficulty,
+            hits: self.hits,
+            best_streak: self.best_streak,
+            ticks: self.game_ticks,
+            level: self.level(),
+        })
+    }
+
+    /// Starts a new game in a new round, with the score and the results of
+    /// the previous game cleared. A challenge starts over from its first
+    /// round.
+    pub fn new_game(&mut self) {
+        if let Some(seed) = self.challenge_seed {
+            self.rng = Random::new(seed);
+            self.time = 0;
+        }
+        self.inputs.clear();
+        self.spin_held = false;
+        self.score = 0;
+        self.deliveries = 0;
+        self.shots = 0;
+        self.losses = 0;
+        self.hits = 0;
+        self.streak = 0;
+        self.best_streak = 0;
+        self.game_ticks = 0;
+        self.game_over = false;
+        self.next_round();
+        self.last_route.clear();
+    }
+
+    /// Counts a round trip and starts the next round, which is harder in
+    /// modes that escalate.
+    fn complete_round(&mut self) {
+        self.deliveries += 1;
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+        if self.config.mode.rules().scores {
+            self.score += 1;
+        }
+        self.push_event(Event::ReturnedToSource {
+            planet: self.packet_source,
+        });
+        self.last_route = std::mem::take(&mut self.route);
+        self.next_round();
+    }
+
+    /// Starts a new round after the previous one was completed or lost,
+    /// with the rules of the new round. Only a completed round is kept as
+    /// the last route.
+    ///
+    /// If no new layout can be generated, the round is played again on the
+    /// previous layout between the same planets, which keeps the capture
+    /// range it was relaxed to.
+    fn next_round(&mut self) {
+        self.last_loss = None;
+        let round_config = self.round_config();
+        let previous_config = std::mem::replace(&mut self.config, round_config);
+        let previous_bodies = std::mem::take(&mut self.bodies);
+        let previous_route = (self.packet_source, self.packet_destination);
+        self.reset();
+        if let Err(err) = self.generate() {
+            if previous_bodies.num_planets() < 2 {
+                throw_generation_error(err);
+            }
+            self.reset();
+            self.bodies = previous_bodies;
+            self.config.planet_activate_range = previous_config.planet_activate_range;
+            self.index_stars();
+            // Only the previous source and destination are sure to be normal
+            // planets that are always online
+            self.packet_source = previous_route.0;
+            self.packet_destination = previous_route.1;
+            self.load_packet();
+        }
+        self.push_event(Event::RoundStarted {
+            attempts: self.generation_attempts,
+        });
     }
 
     fn reset(&mut self) {
         self.packet = Packet::new();
-        self.packet_source = 0;
-        self.packet_destination = 0;
+        self.packet_source = PlanetId::default();
+        self.packet_destination = PlanetId::default();
         self.packet_reached_destination = false;
-        self.star_x.clear();
-        self.star_y.clear();
-        self.star_radius.clear();
-        self.star_system_radius.clear();
-        self.planet_radius.clear();
-        self.planet_star.clear();
-        self.planet_distance.clear();
-        self.planet_q.clear();
-        self.planet_dq.clear();
-        self.planet_direction.clear();
+        self.packet_trail.clear();
+        self.route.clear();
+        self.bodies.clear();
+        self.planets_indexed = false;
+        self.star_grid.clear();
     }
 
     fn num_planets(&self) -> usize {
-        self.planet_star.len()
-    }
-
-    fn generate_stars(&mut self) -> bool {
-        let num_stars = gen_rand(MIN_NUM_STARS, MAX_NUM_STARS);
-        let mut outer_tries = 0;
-        loop {
-            outer_tries += 1;
-            let mut generation_done = true;
-            for _ in 0..num_stars {
-                let mut tries = 0;
-                loop {
-                    tries += 1;
-                    let mut overlap = false;
-                    let x: u32 = gen_rand(0, self.width);
-                    let y: u32 = gen_rand(0, self.height);
-                    let sys_radius: u32 = gen_rand(MIN_STAR_SYSTEM_RADIUS, MAX_STAR_SYSTEM_RADIUS);
-                    for star in 0..self.star_x.len() {
-                        if circles_overlap(
-                            x,
-                            y,
-                            sys_radius,
-                            self.star_x[star],
-                            self.star_y[star],
-                            self.star_system_radius[star],
-                        ) {
-                            overlap = true;
-                            break;
-                        }
-                    }
-                    if !overlap && self.within_window(x, y, sys_radius) {
-                        self.star_x.push(x);
-                        self.star_y.push(y);
-                        self.star_radius
-                            .push(gen_rand(MIN_STAR_RADIUS, MAX_STAR_RADIUS));
-                        self.star_system_radius.push(sys_radius);
-                        break;
-                    } else if tries == MAX_TRIES {
-                        generation_done = false;
-                        break;
-                    }
-                }
-                if !generation_done {
-                    break;
-                }
-            }
-            if generation_done {
-                break;
-            }
-            if outer_tries == MAX_TRIES {
-                return false;
-            }
-            self.star_x.clear();
-            self.star_y.clear();
-            self.star_radius.clear();
-            self.star_system_radius.clear();
+        self.bodies.num_planets()
+    }
+
+    fn generate_planet_angles(&mut self) {
+        for index in 0..self.num_planets() {
+            let q = self.rng.gen_rand(0, 359) as f64;
+            let dq = self
+                .rng
+                .gen_rand(self.config.min_planet_dq, self.config.max_planet_dq)
+                as f64
+                * self.config.slowdown_factor;
+            let direction = self.rng.gen_rand(0, 100) % 2 == 1;
+            self.bodies
+                .set_motion(PlanetId::new(index), q, dq, direction);
         }
-        true
     }
 
-    fn generate_planet_positions(&mut self) -> bool {
-        for star in 0..self.star_x.len() {
-            let mut generation_done;
-            let system_index = self.planet_star.len();
-            let mut outer_tries = 0;
-            loop {
-                outer_tries += 1;
-                generation_done = true;
-                let num_planets_in_star = gen_rand(MIN_NUM_PLANETS, MAX_NUM_PLANETS);
-                for _ in 0..num_planets_in_star {
-                    let mut tries = 0;
-                    loop {
-                        tries += 1;
-                        let mut overlap = false;
-                        let radius: u32 = gen_rand(MIN_PLANET_RADIUS, MAX_PLANET_RADIUS);
-                        let distance: u32 = gen_rand(
-                            self.star_radius[star] + PLANET_ACTIVATE_RANGE * radius,
-                            self.star_system_radius[star] - PLANET_ACTIVATE_RANGE * radius,
-                        );
-                        for planet in system_index..self.planet_star.len() {
-                            if ((distance as i32 - self.planet_distance[planet] as i32).abs())
-                                < ((PLANET_ACTIVATE_RANGE * (radius + self.planet_radius[planet]))
-                                    as i32)
-                            {
-                                overlap = true;
-                                break;
-                            }
-                        }
-                        if !overlap {
-                            self.planet_star.push(star as u8);
-                            self.planet_radius.push(radius);
-                            self.planet_distance.push(distance);
-                            break;
-                        } else if tries == MAX_TRIES {
-                            generation_done = false;
-                            break;
-                        }
-                    }
-                    if !generation_done {
-                        break;
-                    }
-                }
-                if generation_done {
+    fn generate_routers(&mut self) {
+        let config = self.config;
+        for index in 0..self.num_planets() {
+            let capacity = self
+                .rng
+                .gen_rand(config.min_queue_capacity, config.max_queue_capacity);
+            let delay = self
+                .rng
+                .gen_rand(config.min_processing_delay, config.max_processing_delay);
+            self.bodies
+                .set_router(PlanetId::new(index), capacity, delay);
+        }
+    }
+
+    /// Picks the kind of each planet by the chances in the config. The
+    /// source and destination are normal planets.
+    fn generate_kinds(&mut self) {
+        let config = self.config;
+        let chances = [
+            (PlanetKind::Amplifier, config.amplifier_chance),
+            (PlanetKind::Reflector, config.reflector_chance),
+            (PlanetKind::ReverseSpin, config.reverse_spin_chance),
+            (PlanetKind::Sink, config.sink_chance),
+            (PlanetKind::Charger, config.charger_chance),
+        ];
+        for planet in self.bodies.planet_ids() {
+            let mut roll = self.rng.gen_rand(1, 100);
+            if planet == self.packet_source || planet == self.packet_destination {
+                continue;
+            }
+            for &(kind, chance) in chances.iter() {
+                if roll <= chance {
+                    self.bodies.set_kind(planet, kind);
                     break;
                 }
-                if outer_tries == MAX_TRIES {
-                    return false;
-                }
-                self.planet_star.truncate(system_index);
-                self.planet_radius.truncate(system_index);
-                self.planet_distance.truncate(system_index);
+                roll -= chance;
             }
         }
-        true
     }
 
-    fn generate_planet_angles(&mut self) {
-        for _ in 0..self.num_planets() {
-            self.planet_q.push(gen_rand(0, 359) as f64);
-            self.planet_dq
-                .push(gen_rand(MIN_PLANET_DQ, MAX_PLANET_DQ) as f64 * SLOWDOWN_FACTOR);
-            self.planet_direction.push(gen_rand(0, 100) % 2 == 1);
+    /// Puts some planets on a link schedule. The source and destination are
+    /// always online, so that every round can be completed.
+    fn generate_link_windows(&mut self) {
+        let config = self.config;
+        for planet in self.bodies.planet_ids() {
+            if planet == self.packet_source
+                || planet == self.packet_destination
+                || self.rng.gen_rand(1, 100) > config.link_window_chance
+            {
+                continue;
+            }
+            let online = self
+                .rng
+                .gen_rand(config.min_link_online, config.max_link_online);
+            let window = if self.rng.gen_rand(0, 1) == 0 {
+                let period = self
+                    .rng
+                    .gen_rand(config.min_link_period, config.max_link_period);
+                LinkWindow::Periodic {
+                    period,
+                    online: period * online / 100,
+                    phase: self.rng.gen_rand(0, period - 1),
+                }
+            } else {
+                LinkWindow::Orbit {
+                    start: self.rng.gen_rand(0, 359) as f64,
+                    length: 360.0 * online as f64 / 100.0,
+                }
+            };
+            self.bodies.set_link_window(planet, window);
         }
     }
 
     fn generate_packet(&mut self) {
-        let mut source = 0;
-        let mut destination = 0;
-        if self.width > self.height {
-            let mut min = self.planet_x(0);
-            let mut max = self.planet_x(0);
-            for planet in 0..self.num_planets() {
-                let x = self.planet_x(planet);
-                if x < min {
-                    min = x;
-                    source = planet;
-                } else if x > max {
-                    max = x;
-                    destination = planet;
-                }
+        let first = PlanetId::new(0);
+        let mut source = first;
+        let mut destination = first;
+        // Source and destination are the planets furthest apart along the
+        // longer side of the world
+        let position = |planet: PlanetId| {
+            if self.width > self.height {
+                self.planet_x(planet)
+            } else {
+                self.planet_y(planet)
             }
-        } else {
-            let mut min = self.planet_y(0);
-            let mut max = self.planet_y(0);
-            for planet in 0..self.num_planets() {
-                let y = self.planet_y(planet);
-                if y < min {
-                    min = y;
-                    source = planet;
-                } else if y > max {
-                    max = y;
-                    destination = planet;
-                }
+        };
+        let mut min = position(first);
+        let mut max = position(first);
+        for planet in self.bodies.planet_ids() {
+            let value = position(planet);
+            if value < min {
+                min = value;
+                source = planet;
+            } else if value > max {
+                max = value;
+                destination = planet;
             }
         }
         self.packet_source = source;
         self.packet_destination = destination;
-        self.packet = Packet::set_bound(
-            source,
-            self.planet_q[source],
-            self.planet_dq[source],
-            self.planet_direction[source],
-        );
+        self.load_packet();
     }
 
-    fn planet_x(&self, planet: usize) -> f64 {
-        self.star_x[self.planet_star[planet] as usize] as f64
-            + (self.planet_distance[planet] as f64 * self.planet_q[planet].to_radians().cos())
+    /// Picks the payload of the round and puts the packet on the source,
+    /// with the full energy budget.
+    fn load_packet(&mut self) {
+        // The payload must fit through both ends of the route
+        let capacity = self
+            .bodies
+            .planet(self.packet_source)
+            .queue_capacity()
+            .min(self.bodies.planet(self.packet_destination).queue_capacity());
+        self.packet_size = self
+            .rng
+            .gen_rand(self.config.min_payload_size, self.config.max_payload_size)
+            .min(capacity);
+        self.energy = self.config.energy_budget as f64;
+        self.restart_packet();
+    }
+
+    /// Puts the packet back on the source with a fresh TTL, at the start of
+    /// a round or after it was lost.
+    fn restart_packet(&mut self) {
+        self.packet_reached_destination = false;
+        self.packet_ttl = self.config.packet_ttl;
+        self.packet_hold = 0;
+        self.packet = self.bind_packet(self.packet_source);
+        self.route.clear();
+        self.add_hop(self.packet_source);
+    }
+
+    fn lose_packet(&mut self, reason: LossReason) {
+        self.record_loss(reason);
+        self.restart_packet();
+    }
+
+    /// Counts a loss, which ends the game once the lives run out.
+    fn record_loss(&mut self, reason: LossReason) {
+        self.last_loss = Some(reason);
+        self.losses += 1;
+        self.streak = 0;
+        self.push_event(Event::Lost { reason });
+        if let Some(0) = self.lives_left() {
+            self.finish_game();
+        }
     }
 
-    fn planet_y(&self, planet: usize) -> f64 {
-        self.star_y[self.planet_star[planet] as usize] as f64
-            + (self.planet_distance[planet] as f64 * self.planet_q[planet].to_radians().sin())
+    /// Returns how the packet was last lost in this round, if it was.
+    pub fn last_loss(&self) -> Option<LossReason> {
+        self.last_loss
+    }
+
+    /// Binds the packet to a planet that captured it, which holds it while
+    /// processing and transmitting the payload.
+    fn route_packet(&mut self, planet: PlanetId) {
+        let router = self.bodies.planet(planet);
+        self.hits += 1;
+        self.packet_hold =
+            router.processing_delay() + self.packet_size.div_ceil(self.config.bandwidth);
+        if router.kind().rules().recharges {
+            self.energy = (self.energy + self.config.charger_energy as f64)
+                .min(self.config.energy_budget as f64);
+        }
+        self.packet = self.bind_packet(planet);
+        self.add_hop(planet);
+        self.push_event(Event::Captured { planet });
+    }
+
+    fn bind_packet(&self, planet: PlanetId) -> Packet {
+        let view = self.bodies.planet(planet);
+        let direction = view.direction() != view.kind().rules().reverses_spin;
+        Packet::set_bound(planet, view.q(), view.dq(), direction, &self.config)
+    }
+
+    fn add_hop(&mut self, planet: PlanetId) {
+        self.route
+            .push((planet, self.planet_x(planet), self.planet_y(planet)));
+    }
+
+    fn planet_x(&self, planet: PlanetId) -> f64 {
+        self.bodies.planet(planet).x()
+    }
+
+    fn planet_y(&self, planet: PlanetId) -> f64 {
+        self.bodies.planet(planet).y()
     }
 
     fn packet_bound(&self) -> bool {
         self.packet.is_bound()
     }
 
-    pub fn packet_end_x(&self) -> f64 {
+    fn window_end_x(&self) -> f64 {
         match self.packet {
             Packet::Bound {
                 planet,
@@ -343,7 +808,7 @@ impl Universe {
         }
     }
 
-    pub fn packet_end_y(&self) -> f64 {
+    fn window_end_y(&self) -> f64 {
         match self.packet {
             Packet::Bound {
                 planet,
@@ -405,6 +870,46 @@ impl Universe {
         }
     }
 
+    /// Returns the end of the aim line, which is where the packet would
+    /// leave the window or hit the first star in its way.
+    pub fn packet_end_x(&self) -> f64 {
+        self.aim_end().0
+    }
+
+    pub fn packet_end_y(&self) -> f64 {
+        self.aim_end().1
+    }
+
+    fn aim_end(&self) -> (f64, f64) {
+        let (end_x, end_y) = (self.window_end_x(), self.window_end_y());
+        let planet = match self.packet.get_bound_planet() {
+            Some(planet) => planet,
+            None => return (end_x, end_y),
+        };
+        let (x, y) = (self.planet_x(planet), self.planet_y(planet));
+        let length = ((end_x - x).powi(2) + (end_y - y).powi(2)).sqrt();
+        if length == 0.0 {
+            return (end_x, end_y);
+        }
+        let (dx, dy) = ((end_x - x) / length, (end_y - y) / length);
+        let mut hit = length;
+        for star in self.bodies.stars() {
+            // Distance along the ray to the first point within absorbing
+            // range of the star
+            let (cx, cy) = (star.x() as f64 - x, star.y() as f64 - y);
+            let along = cx * dx + cy * dy;
+            let across = cx * cx + cy * cy - along * along;
+            let radius = self.absorb_radius(star.id());
+            if across < radius * radius {
+                let distance = along - (radius * radius - across).sqrt();
+                if distance >= 0.0 && distance < hit {
+                    hit = distance;
+                }
+            }
+        }
+        (x + dx * hit, y + dy * hit)
+    }
+
     pub fn packet_x(&self) -> f64 {
         match self.packet {
             Packet::Bound {
@@ -441,6 +946,24 @@ impl Universe {
         }
     }
 
+    fn packet_position(&self) -> (f64, f64) {
+        match self.packet {
+            Packet::Bound {
+                planet,
+                q: _,
+                dq: _,
+                direction: _,
+            } => (self.planet_x(planet), self.planet_y(planet)),
+            Packet::Free {
+                x,
+                y,
+                dx: _,
+                dy: _,
+                last_planet: _,
+            } => (x, y),
+        }
+    }
+
     fn within_window(&self, x: u32, y: u32, radius: u32) -> bool {
         !((x as i32 - radius as i32) < 0
             || (x as i32 + radius as i32) > self.width as i32
@@ -448,7 +971,18 @@ impl Universe {
             || (y as i32 + radius as i32) > self.height as i32)
     }
 
+    /// Sends the packet on from the planet holding it, unless the planet is
+    /// still processing it or is offline.
     pub fn free_packet(&mut self) {
+        self.record_input(Input::FreePacket);
+        if self.game_over || self.packet_hold > 0 {
+            return;
+        }
+        if let Some(planet) = self.packet.get_bound_planet() {
+            if !self.bodies.planet(planet).is_online(self.time) {
+                return;
+            }
+        }
         match self.packet {
             Packet::Bound {
                 planet,
@@ -456,13 +990,19 @@ impl Universe {
                 dq: _,
                 direction: _,
             } => {
+                let mut speed = self.config.packet_speed;
+                if self.bodies.planet(planet).kind().rules().boosts {
+                    speed *= self.config.amplifier_boost;
+                }
+                self.shots += 1;
                 self.packet = Packet::set_free(
                     self.planet_x(planet),
                     self.planet_y(planet),
-                    PACKET_SPEED * q.to_radians().cos(),
-                    -PACKET_SPEED * q.to_radians().sin(),
+                    speed * q.to_radians().cos(),
+                    -speed * q.to_radians().sin(),
                     planet,
                 );
+                self.push_event(Event::Released { planet });
             }
             Packet::Free {
                 x: _,
@@ -474,65 +1014,1019 @@ impl Universe {
         }
     }
 
-    pub fn tick(&mut self) -> *const u32 {
-        let mut planet_data: Vec<u32> = Vec::new();
-        for planet in 0..self.num_planets() {
-            if self.planet_direction[planet] {
-                self.planet_q[planet] = self.planet_q[planet] + self.planet_dq[planet];
-            } else {
-                self.planet_q[planet] = self.planet_q[planet] + (360.0 - self.planet_dq[planet]);
+    /// Reverses the rotation of the bound packet, if the player controls
+    /// the spin.
+    pub fn reverse_spin(&mut self) {
+        self.record_input(Input::ReverseSpin);
+        if self.config.manual_spin {
+            self.packet.reverse_spin();
+        }
+    }
+
+    /// Speeds up the rotation of the bound packet by one step, if the
+    /// player controls the spin.
+    pub fn speed_up_spin(&mut self) {
+        self.record_input(Input::SpeedUpSpin);
+        self.change_spin(self.config.spin_step);
+    }
+
+    /// Slows down the rotation of the bound packet by one step, if the
+    /// player controls the spin.
+    pub fn slow_down_spin(&mut self) {
+        self.record_input(Input::SlowDownSpin);
+        self.change_spin(-self.config.spin_step);
+    }
+
+    fn change_spin(&mut self, delta: f64) {
+        if self.config.manual_spin {
+            self.packet
+                .change_spin(delta, self.config.min_spin_dq, self.config.max_spin_dq);
+        }
+    }
+
+    /// Stops the rotation of a bound packet while `held` is set, if the
+    /// player controls the spin.
+    pub fn set_spin_held(&mut self, held: bool) {
+        self.record_input(if held {
+            Input::HoldSpin
+        } else {
+            Input::ReleaseSpin
+        });
+        self.spin_held = held;
+    }
+
+    /// Fits the visible area of the universe into a viewport of the given
+    /// size in pixels.
+    ///
+    /// The universe keeps the logical size it was created with, and is
+    /// scaled uniformly around the camera, so frame data is in viewport
+    /// pixels while the layout and the game state are unchanged.
+    /// Empty viewports are ignored, keeping the previous size.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // A minimized window or hidden frame has no area to fit into
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.viewport_width = width;
+        self.viewport_height = height;
+        self.view_scale =
+            (width as f64 / self.view_width as f64).min(height as f64 / self.view_height as f64);
+        self.clamp_camera();
+    }
+
+    /// Moves the camera by the given amount of viewport pixels and stops
+    /// following the packet.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        let scale = self.view_scale * self.zoom;
+        self.camera_x += dx / scale;
+        self.camera_y += dy / scale;
+        self.follow_packet = false;
+        self.clamp_camera();
+    }
+
+    pub fn set_zoom(&mut self, zoom: f64) {
+        if zoom.is_nan() {
+            return;
+        }
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.clamp_camera();
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    pub fn set_follow_packet(&mut self, follow: bool) {
+        self.follow_packet = follow;
+    }
+
+    fn update_camera(&mut self) {
+        if self.follow_packet {
+            let (x, y) = self.packet_position();
+            self.camera_x += (x - self.camera_x) * CAMERA_FOLLOW_RATE;
+            self.camera_y += (y - self.camera_y) * CAMERA_FOLLOW_RATE;
+        }
+        self.clamp_camera();
+    }
+
+    /// Keeps the camera within the world, or centered on the world along
+    /// the axes where the whole world is visible.
+    fn clamp_camera(&mut self) {
+        let scale = self.view_scale * self.zoom;
+        let half_width = self.viewport_width as f64 / scale / 2.0;
+        let half_height = self.viewport_height as f64 / scale / 2.0;
+        self.camera_x = clamp_axis(self.camera_x, half_width, self.width as f64);
+        self.camera_y = clamp_axis(self.camera_y, half_height, self.height as f64);
+    }
+
+    pub fn set_show_orbits(&mut self, show: bool) {
+        self.show_orbits = show;
+    }
+
+    pub fn set_show_system_boundaries(&mut self, show: bool) {
+        self.show_system_boundaries = show;
+    }
+
+    /// Adds the data of the debug overlay to the frame, see
+    /// [`Universe::tick`].
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Returns the route taken in the last completed round as
+    /// (planet, x, y) triples, one for each planet the packet was bound to.
+    pub fn last_route(&self) -> Vec<u32> {
+        let mut route = Vec::with_capacity(self.last_route.len() * 3);
+        for (planet, x, y) in self.last_route.iter() {
+            route.push(planet.index() as u32);
+            route.push(*x as u32);
+            route.push(*y as u32);
+        }
+        route
+    }
+
+    /// Returns the layout of the universe as an SVG document, with planets
+    /// at their current positions.
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        );
+        let _ = writeln!(
+            svg,
+            r##"<rect width="100%" height="100%" fill="#070219"/>"##
+        );
+        for star in self.bodies.stars() {
+            let (x, y) = (star.x(), star.y());
+            let _ = writeln!(
+                svg,
+                r#"<g class="system" id="system-{}">"#,
+                star.id().index()
+            );
+            let _ = writeln!(
+                svg,
+                r##"<circle class="boundary" cx="{}" cy="{}" r="{}" fill="none" stroke="#eaea8a" stroke-opacity="0.1"/>"##,
+                x,
+                y,
+                star.system_radius()
+            );
+            for planet in star.planets() {
+                let (cx, cy) = planet.ellipse_center();
+                let _ = writeln!(
+                    svg,
+                    r##"<ellipse class="orbit" cx="{:.1}" cy="{:.1}" rx="{}" ry="{:.1}" transform="rotate({:.1} {:.1} {:.1})" fill="none" stroke="#cbf1f2" stroke-opacity="0.2"/>"##,
+                    cx,
+                    cy,
+                    planet.distance(),
+                    planet.semi_minor_axis(),
+                    planet.periapsis(),
+                    cx,
+                    cy
+                );
             }
-            if self.planet_q[planet] > 360.0 {
-                self.planet_q[planet] -= 360.0;
+            let _ = writeln!(
+                svg,
+                r##"<circle class="star" id="star-{}" cx="{}" cy="{}" r="{}" fill="#eaea8a"/>"##,
+                star.id().index(),
+                x,
+                y,
+                star.radius()
+            );
+            let _ = writeln!(svg, "</g>");
+        }
+        for (class, planet) in [
+            ("destination", self.packet_destination),
+            ("source", self.packet_source),
+        ]
+        .iter()
+        {
+            let planet = self.bodies.planet(*planet);
+            let _ = writeln!(
+                svg,
+                r##"<circle class="{}" cx="{:.1}" cy="{:.1}" r="{:.1}" fill="#00ff00"/>"##,
+                class,
+                planet.x(),
+                planet.y(),
+                planet.radius() as f64 * 1.8
+            );
+        }
+        for planet in self.bodies.planets() {
+            let _ = writeln!(
+                svg,
+                r##"<circle class="planet" id="planet-{}" cx="{:.1}" cy="{:.1}" r="{}" fill="#cbf1f2"/>"##,
+                planet.id().index(),
+                planet.x(),
+                planet.y(),
+                planet.radius()
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Advances the universe by one frame and returns a pointer to the frame data.
+    ///
+    /// A frame advances the game by as many ticks as the time scale adds up
+    /// to, so that in slow motion some frames do not advance it at all, and
+    /// none do while the game is paused.
+    ///
+    /// Positions and radii are in viewport pixels as seen through the camera,
+    /// see [`Universe::resize`]. Positions are signed, as bodies outside the
+    /// viewport can have negative coordinates.
+    ///
+    /// Layout of the frame data (all values are 32 bit integers):
+    /// - length of the frame, score, ticks left before the packet expires,
+    ///   payload size, ticks left before the packet can be sent on, energy
+    ///   left in the round, energy budget (0 if energy is unlimited)
+    /// - orbit count, followed by (center x, center y, semi-major axis,
+    ///   semi-minor axis, rotation in degrees) for each orbit ellipse
+    /// - boundary count, followed by (star x, star y, radius) for each star system
+    /// - trail point count, followed by (x, y) for recent packet positions
+    /// - route point count, followed by (x, y) for each hop in this round
+    /// - 1 followed by the aim line (x1, y1, x2, y2) if the packet is bound, or 0
+    /// - six groups of circles (packet, source/destination, stars, planets,
+    ///   planets whose queue is too small for the payload, planets outside
+    ///   their link window), each a count followed by (x, y, radius) for each
+    ///   circle
+    /// - special planet count, followed by (x, y, radius, kind) for each
+    ///   planet that is not of the normal kind, with the kind numbered as in
+    ///   [`PlanetKind`]
+    /// - 1 followed by the debug overlay if debugging is on, or 0: attempts it
+    ///   took to generate the round, index of the planet the packet was last
+    ///   sent from (-1 if none), the velocity vector of the packet (x1, y1,
+    ///   x2, y2), and planet count followed by (x, y, capture radius, index)
+    ///   for each planet
+    pub fn tick(&mut self) -> *const u32 {
+        if !self.paused {
+            self.pending_time += self.time_scale;
+            while self.pending_time >= 1.0 {
+                self.pending_time -= 1.0;
+                self.advance();
             }
-            planet_data.push(self.planet_x(planet) as u32);
-            planet_data.push(self.planet_y(planet) as u32);
-            planet_data.push(self.planet_radius[planet] as u32);
         }
-        let (within_window, packet) = Packet::tick(self.packet, self.width, self.height);
+        self.update_camera();
+        self.write_frame();
+        self.frame.as_ptr()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advances a paused game by a single tick, which shows in the next
+    /// frame.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.advance();
+        }
+    }
+
+    /// Sets the ticks the game advances by in each frame, below 1 for slow
+    /// motion. NaN is ignored.
+    pub fn set_time_scale(&mut self, scale: f64) {
+        if scale.is_nan() {
+            return;
+        }
+        self.time_scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Advances the game by one tick.
+    pub(crate) fn advance(&mut self) {
+        if self.game_over {
+            // The universe stands still until a new game starts
+            return;
+        }
+        self.game_ticks += 1;
+        if let Some(0) = self.ticks_left() {
+            // Nothing happens after the time runs out, so that the results
+            // match the game over event
+            self.finish_game();
+            return;
+        }
+        self.time = self.time.wrapping_add(1);
+        self.bodies.advance_orbits();
+        let (within_window, packet) = Packet::tick(
+            self.packet,
+            self.width,
+            self.height,
+            self.config.manual_spin && self.spin_held,
+        );
         if !within_window {
-            self.packet_reached_destination = false;
-            self.packet = Packet::set_bound(
-                self.packet_source,
-                self.planet_q[self.packet_source],
-                self.planet_dq[self.packet_source],
-                self.planet_direction[self.packet_source],
-            );
+            self.lose_packet(LossReason::LeftWindow);
         } else {
             self.packet = packet;
         }
+        if self.config.mode.rules().fails
+            && self.packet.get_bound_planet() != Some(self.packet_source)
+        {
+            // The packet expires when its TTL runs out away from the source
+            self.packet_ttl -= 1;
+            if self.packet_ttl == 0 {
+                self.lose_packet(LossReason::Expired);
+            }
+        }
+        self.packet_hold = self.packet_hold.saturating_sub(1);
         if !self.packet_bound() {
             let px = self.packet_x();
             let py = self.packet_y();
-            for planet in 0..self.num_planets() {
-                if planet != self.packet.get_last_planet()
-                    && (px - self.planet_x(planet)).powf(2.0)
-                        + (py - self.planet_y(planet)).powf(2.0)
-                        < (self.planet_radius[planet] as f64 * PLANET_ACTIVATE_RANGE as f64
-                            + PACKET_RADIUS as f64)
-                            .powf(2.0)
-                {
-                    self.packet = Packet::set_bound(
-                        planet,
-                        self.planet_q[planet],
-                        self.planet_dq[planet],
-                        self.planet_direction[planet],
-                    );
-                    if !self.packet_reached_destination && planet == self.packet_destination {
-                        self.packet_reached_destination = true;
+            self.packet_trail.push_back((px, py));
+            while self.packet_trail.len() > self.config.trail_length as usize {
+                self.packet_trail.pop_front();
+            }
+            if self.star_hit(px, py) {
+                self.lose_packet(LossReason::Absorbed);
+            } else if !self.spend_energy() {
+                self.record_loss(LossReason::OutOfEnergy);
+                self.next_round();
+                // Keep showing why the previous round ended
+                self.last_loss = Some(LossReason::OutOfEnergy);
+            } else {
+                if !self.planets_indexed {
+                    self.index_planets();
+                }
+                self.planet_grid.query(px, py, 0.0, &mut self.candidates);
+                for index in 0..self.candidates.len() {
+                    let planet = PlanetId::new(self.candidates[index]);
+                    if Some(planet) != self.packet.get_last_planet()
+                        && self.bodies.planet(planet).is_online(self.time)
+                        && (px - self.planet_x(planet)).powf(2.0)
+                            + (py - self.planet_y(planet)).powf(2.0)
+                            < self.capture_radius(planet).powf(2.0)
+                    {
+                        let rules = self.bodies.planet(planet).kind().rules();
+                        if rules.swallows {
+                            self.lose_packet(LossReason::Swallowed);
+                            break;
+                        }
+                        if rules.reflects {
+                            self.reflect_packet(planet);
+                            break;
+                        }
+                        if self.packet_size > self.bodies.planet(planet).queue_capacity() {
+                            // The queue of the planet overflows and drops the packet
+                            self.lose_packet(LossReason::Dropped);
+                            break;
+                        }
+                        self.route_packet(planet);
+                        if !self.packet_reached_destination && planet == self.packet_destination {
+                            self.packet_reached_destination = true;
+                            self.push_event(Event::ReachedDestination { planet });
+                        }
+                        if self.packet_reached_destination && planet == self.packet_source {
+                            self.complete_round();
+                        }
+                        break;
+                    }
+                }
+            }
+        } else {
+            // Let the trail fade out while the packet is bound
+            self.packet_trail.pop_front();
+        }
+    }
+
+    /// Re-emits a free packet from a reflector, mirroring its direction
+    /// about the line from the planet to the packet.
+    fn reflect_packet(&mut self, planet: PlanetId) {
+        if let Packet::Free {
+            x,
+            y,
+            dx,
+            dy,
+            last_planet: _,
+        } = self.packet
+        {
+            let (nx, ny) = (x - self.planet_x(planet), y - self.planet_y(planet));
+            let length = (nx * nx + ny * ny).sqrt();
+            let (dx, dy) = if length > 0.0 {
+                let (nx, ny) = (nx / length, ny / length);
+                let dot = dx * nx + dy * ny;
+                (dx - 2.0 * dot * nx, dy - 2.0 * dot * ny)
+            } else {
+                (-dx, -dy)
+            };
+            self.add_hop(planet);
+            self.packet = Packet::set_free(x, y, dx, dy, planet);
+            self.push_event(Event::Reflected { planet });
+        }
+    }
+
+    /// Spends the energy for the distance the packet moved in this tick.
+    /// Returns false if the energy of the round ran out.
+    fn spend_energy(&mut self) -> bool {
+        if self.config.energy_budget == 0 || !self.config.mode.rules().fails {
+            return true;
+        }
+        let (dx, dy) = self.packet.velocity();
+        self.energy -= (dx * dx + dy * dy).sqrt() * self.config.energy_per_distance;
+        self.energy > 0.0
+    }
+
+    /// Distance from a star within which it absorbs the packet.
+    fn absorb_radius(&self, star: StarId) -> f64 {
+        self.bodies.star(star).radius() as f64 + self.config.packet_radius as f64
+    }
+
+    /// Returns whether a packet at the given position strikes a star.
+    fn star_hit(&mut self, x: f64, y: f64) -> bool {
+        self.star_grid.query(x, y, 0.0, &mut self.candidates);
+        self.candidates.iter().any(|&star| {
+            let star = StarId::new(star);
+            let view = self.bodies.star(star);
+            (x - view.x() as f64).powi(2) + (y - view.y() as f64).powi(2)
+                < self.absorb_radius(star).powi(2)
+        })
+    }
+
+    fn capture_radius(&self, planet: PlanetId) -> f64 {
+        self.bodies.planet(planet).radius() as f64 * self.config.planet_activate_range as f64
+            + self.config.packet_radius as f64
+    }
+
+    /// Rebuilds the spatial index of the capture areas of the planets, as
+    /// the rings around their stars that the areas sweep while orbiting.
+    fn index_planets(&mut self) {
+        self.planet_grid.clear();
+        for planet in self.bodies.planets() {
+            let star = planet.star();
+            let (near, far) = planet.reach_range();
+            let capture_radius = self.capture_radius(planet.id());
+            self.planet_grid.insert_annulus(
+                planet.id().index(),
+                star.x() as f64,
+                star.y() as f64,
+                near - capture_radius,
+                far + capture_radius,
+            );
+        }
+        self.planets_indexed = true;
+    }
+
+    fn write_frame(&mut self) {
+        let mut data = std::mem::take(&mut self.frame);
+        self.build_frame(&mut data);
+        self.frame = data;
+    }
+}
+
+impl Universe {
+    /// Creates a universe like [`Universe::with_config`], returning an error
+    /// instead of throwing if the first round cannot be generated.
+    pub fn try_create(
+        width: u32,
+        height: u32,
+        world_width: u32,
+        world_height: u32,
+        seed: u32,
+        config: GameConfig,
+    ) -> Result<Universe, GenerationError> {
+        let config = config.sanitized();
+        let mut universe = Universe {
+            rng: Random::new(seed),
+            base_config: config,
+            config,
+            generation_attempts: 0,
+            min_system_radius: config.min_star_system_radius,
+            max_system_radius: config.max_star_system_radius,
+            min_num_stars: config.min_num_stars,
+            max_num_stars: config.max_num_stars,
+            width: world_width,
+            height: world_height,
+            view_width: width,
+            view_height: height,
+            viewport_width: width,
+            viewport_height: height,
+            view_scale: 1.0,
+            camera_x: world_width as f64 / 2.0,
+            camera_y: world_height as f64 / 2.0,
+            zoom: 1.0,
+            follow_packet: world_width > width || world_height > height,
+            score: 0,
+            deliveries: 0,
+            shots: 0,
+            losses: 0,
+            hits: 0,
+            streak: 0,
+            best_streak: 0,
+            game_ticks: 0,
+            game_over: false,
+            paused: false,
+            time_scale: 1.0,
+            pending_time: 0.0,
+            challenge_seed: None,
+            inputs: Vec::new(),
+            events: VecDeque::new(),
+            packet: Packet::new(),
+            packet_source: PlanetId::default(),
+            packet_destination: PlanetId::default(),
+            packet_reached_destination: false,
+            packet_size: 0,
+            packet_ttl: 0,
+            packet_hold: 0,
+            energy: 0.0,
+            last_loss: None,
+            spin_held: false,
+            time: 0,
+            bodies: Bodies::new(),
+            star_grid: SpatialGrid::new(
+                world_width,
+                world_height,
+                config.max_star_system_radius * 2,
+            ),
+            planet_grid: SpatialGrid::new(world_width, world_height, config.capture_cell_size()),
+            planets_indexed: false,
+            candidates: Vec::new(),
+            packet_trail: VecDeque::with_capacity(config.trail_length as usize),
+            route: Vec::new(),
+            last_route: Vec::new(),
+            show_orbits: true,
+            show_system_boundaries: true,
+            debug: false,
+            frame: Vec::new(),
+        };
+        universe.config = universe.round_config();
+        universe.relax_constraints()?;
+        universe.generate()?;
+        Ok(universe)
+    }
+
+    /// Creates the universe of a challenge like [`Universe::challenge`],
+    /// returning an error instead of throwing.
+    pub fn try_create_challenge(seed: u32) -> Result<Universe, GenerationError> {
+        let mut universe = Universe::try_create(
+            CHALLENGE_WIDTH,
+            CHALLENGE_HEIGHT,
+            CHALLENGE_WIDTH,
+            CHALLENGE_HEIGHT,
+            seed,
+            challenge::challenge_config(),
+        )?;
+        universe.challenge_seed = Some(seed);
+        Ok(universe)
+    }
+
+    /// Changes the rules like [`Universe::set_config`], returning an error
+    /// instead of throwing if the world is too small for them.
+    pub fn try_set_config(&mut self, config: GameConfig) -> Result<(), GenerationError> {
+        let previous = std::mem::replace(&mut self.base_config, config.sanitized());
+        self.config = self.round_config();
+        if let Err(err) = self.relax_constraints() {
+            self.base_config = previous;
+            self.config = self.round_config();
+            let _ = self.relax_constraints();
+            return Err(err);
+        }
+        self.planet_grid =
+            SpatialGrid::new(self.width, self.height, self.config.capture_cell_size());
+        self.planets_indexed = false;
+        Ok(())
+    }
+
+    /// Returns the events since the last call, oldest first, and clears the
+    /// queue, like [`Universe::drain_events`].
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+
+    /// Returns the number of ticks played in the current game.
+    pub fn game_ticks(&self) -> u32 {
+        self.game_ticks
+    }
+
+    /// Performs a recorded action of the player.
+    pub fn apply_input(&mut self, input: Input) {
+        match input {
+            Input::FreePacket => self.free_packet(),
+            Input::ReverseSpin => self.reverse_spin(),
+            Input::SpeedUpSpin => self.speed_up_spin(),
+            Input::SlowDownSpin => self.slow_down_spin(),
+            Input::HoldSpin => self.set_spin_held(true),
+            Input::ReleaseSpin => self.set_spin_held(false),
+            Input::EndGame => self.end_game(),
+        }
+    }
+
+    fn record_input(&mut self, input: Input) {
+        if !self.game_over {
+            self.inputs.push((self.game_ticks, input));
+        }
+    }
+
+    /// Returns the number of attempts it took to generate the current round.
+    pub fn generation_attempts(&self) -> u32 {
+        self.generation_attempts
+    }
+
+    pub(crate) fn system_radius_range(&self) -> (u32, u32) {
+        (self.min_system_radius, self.max_system_radius)
+    }
+
+    pub(crate) fn star_count_range(&self) -> (u32, u32) {
+        (self.min_num_stars, self.max_num_stars)
+    }
+
+    pub(crate) fn rand(&mut self, start: u32, end: u32) -> u32 {
+        self.rng.gen_rand(start, end)
+    }
+
+    pub(crate) fn world_width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn world_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bodies(&self) -> &Bodies {
+        &self.bodies
+    }
+
+    /// Adds a star at runtime, without checking for overlap with the
+    /// systems of other stars.
+    pub fn add_star(&mut self, x: u32, y: u32, radius: u32, system_radius: u32) -> StarId {
+        let star = self.bodies.add_star(x, y, radius, system_radius);
+        self.star_grid
+            .insert(star.index(), x as f64, y as f64, system_radius as f64);
+        star
+    }
+
+    /// Adds a planet at runtime on a circular orbit around `star` at
+    /// `distance`, starting at angle `q` and moving `dq` degrees in each
+    /// tick.
+    pub fn add_planet(
+        &mut self,
+        star: StarId,
+        radius: u32,
+        distance: u32,
+        q: f64,
+        dq: f64,
+        direction: bool,
+    ) -> PlanetId {
+        let planet = self.bodies.add_planet(star, radius, distance);
+        self.bodies.set_motion(planet, q, dq, direction);
+        self.planets_indexed = false;
+        planet
+    }
+
+    pub fn set_planet_kind(&mut self, planet: PlanetId, kind: PlanetKind) {
+        self.bodies.set_kind(planet, kind);
+    }
+
+    /// Puts a planet on a link schedule at runtime, see [`LinkWindow`].
+    pub fn set_link_window(&mut self, planet: PlanetId, window: LinkWindow) {
+        self.bodies.set_link_window(planet, window);
+    }
+
+    /// Returns the number of ticks since the universe was created, which
+    /// link windows are scheduled by.
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// Makes the orbit of a planet elliptical, with the given eccentricity
+    /// and periapsis angle in degrees. The orbit keeps its semi-major axis.
+    pub fn set_orbit(&mut self, planet: PlanetId, eccentricity: f64, periapsis: f64) {
+        self.bodies.set_orbit(planet, eccentricity, periapsis);
+        self.planets_indexed = false;
+    }
+
+    /// Adds a moon at runtime, orbiting `parent` at `distance`, starting at
+    /// angle `q` and moving `dq` degrees in each tick.
+    pub fn add_moon(
+        &mut self,
+        parent: PlanetId,
+        radius: u32,
+        distance: u32,
+        q: f64,
+        dq: f64,
+        direction: bool,
+    ) -> PlanetId {
+        let moon = self.bodies.add_moon(parent, radius, distance);
+        self.bodies.set_motion(moon, q, dq, direction);
+        self.planets_indexed = false;
+        moon
+    }
+
+    /// Removes a planet and its moons at runtime. The source and destination
+    /// planets, and the planet holding the packet, cannot be removed, nor
+    /// can the planets they orbit. Returns whether the planet was removed.
+    ///
+    /// The last planets take over the ids of the removed planets.
+    pub fn remove_planet(&mut self, planet: PlanetId) -> bool {
+        if planet.index() >= self.num_planets() {
+            return false;
+        }
+        self.detach_planets(self.with_moons(vec![planet]))
+    }
+
+    /// Removes a star and all of its planets at runtime. Stars with the
+    /// source or destination planet, or the planet holding the packet,
+    /// cannot be removed. Returns whether the star was removed.
+    ///
+    /// The last star takes over the id of the removed star.
+    pub fn remove_star(&mut self, star: StarId) -> bool {
+        if star.index() >= self.bodies.num_stars() {
+            return false;
+        }
+        let planets = self
+            .bodies
+            .star(star)
+            .planets()
+            .filter(|planet| planet.parent().is_none())
+            .map(|planet| planet.id())
+            .collect();
+        if !self.detach_planets(self.with_moons(planets)) {
+            return false;
+        }
+        self.bodies.remove_star(star);
+        self.index_stars();
+        true
+    }
+
+    /// Rebuilds the spatial index of the star systems.
+    fn index_stars(&mut self) {
+        self.star_grid.clear();
+        for star in self.bodies.stars() {
+            self.star_grid.insert(
+                star.id().index(),
+                star.x() as f64,
+                star.y() as f64,
+                star.system_radius() as f64,
+            );
+        }
+    }
+
+    /// Adds the moons of the given planets and their moons, each after the
+    /// planet it orbits.
+    fn with_moons(&self, mut planets: Vec<PlanetId>) -> Vec<PlanetId> {
+        let mut index = 0;
+        while index < planets.len() {
+            let moons = self.bodies.planet(planets[index]).moons();
+            planets.extend(moons.map(|moon| moon.id()));
+            index += 1;
+        }
+        planets
+    }
+
+    /// Removes a set of planets made by [`Universe::with_moons`], unless it
+    /// contains the source, destination or the planet holding the packet.
+    fn detach_planets(&mut self, mut planets: Vec<PlanetId>) -> bool {
+        let protected = [
+            Some(self.packet_source),
+            Some(self.packet_destination),
+            self.packet.get_bound_planet(),
+        ];
+        if planets
+            .iter()
+            .any(|planet| protected.contains(&Some(*planet)))
+        {
+            return false;
+        }
+        self.planets_indexed = false;
+        // Remove the moons before the planets they orbit. Ids do not tell
+        // them apart, as earlier removals may have moved a moon below its
+        // parent.
+        while let Some(planet) = planets.pop() {
+            self.route.retain(|(hop, _, _)| *hop != planet);
+            self.last_route.retain(|(hop, _, _)| *hop != planet);
+            self.packet.replace_planet(planet, None);
+            if let Some(moved) = self.bodies.remove_planet(planet) {
+                let remap = |id: &mut PlanetId| {
+                    if *id == moved {
+                        *id = planet;
                     }
-                    if self.packet_reached_destination && planet == self.packet_source {
-                        self.score += 1;
-                        self.reset();
-                        self.generate();
+                };
+                remap(&mut self.packet_source);
+                remap(&mut self.packet_destination);
+                planets.iter_mut().for_each(remap);
+                for (hop, _, _) in self.route.iter_mut().chain(self.last_route.iter_mut()) {
+                    remap(hop);
+                }
+                self.packet.replace_planet(moved, Some(planet));
+            }
+        }
+        true
+    }
+
+    pub(crate) fn num_stars(&self) -> usize {
+        self.bodies.num_stars()
+    }
+
+    /// Returns the position and the system radius of a star.
+    pub(crate) fn star_system(&self, star: StarId) -> (u32, u32, u32) {
+        let star = self.bodies.star(star);
+        (star.x(), star.y(), star.system_radius())
+    }
+
+    /// Adds a star system if it lies within the world and does not overlap
+    /// any other star system.
+    pub(crate) fn place_star(&mut self, x: u32, y: u32, sys_radius: u32) -> Option<StarId> {
+        self.star_grid
+            .query(x as f64, y as f64, sys_radius as f64, &mut self.candidates);
+        for &star in self.candidates.iter() {
+            let star = self.bodies.star(StarId::new(star));
+            if circles_overlap(x, y, sys_radius, star.x(), star.y(), star.system_radius()) {
+                return None;
+            }
+        }
+        if !self.within_window(x, y, sys_radius) {
+            return None;
+        }
+        let radius = self
+            .rng
+            .gen_rand(self.config.min_star_radius, self.config.max_star_radius);
+        Some(self.add_star(x, y, radius, sys_radius))
+    }
+
+    pub(crate) fn clear_stars(&mut self) {
+        self.bodies.clear_stars();
+        self.star_grid.clear();
+    }
+
+    /// Places planets on orbits around each star, some of them with moons.
+    ///
+    /// Each planet occupies a band of distances from its star, between its
+    /// periapsis and apoapsis and wide enough for the capture areas of the
+    /// planet and its moons. The bands of a star system do not overlap, so
+    /// neither do the orbits.
+    pub(crate) fn generate_planet_positions(&mut self) -> Result<(), GenerationError> {
+        let mut bands: Vec<(f64, f64)> = Vec::new();
+        let mut moons: Vec<(u32, u32)> = Vec::new();
+        for index in 0..self.bodies.num_stars() {
+            let star = StarId::new(index);
+            let star_radius = self.bodies.star(star).radius();
+            let system_radius = self.bodies.star(star).system_radius();
+            let max_reach = (system_radius - star_radius) / 2;
+            let mut generation_done;
+            let system_index = self.num_planets();
+            let mut outer_tries = 0;
+            loop {
+                outer_tries += 1;
+                generation_done = true;
+                bands.clear();
+                let num_planets_in_star = self
+                    .rng
+                    .gen_rand(self.config.min_num_planets, self.config.max_num_planets);
+                for _ in 0..num_planets_in_star {
+                    let mut tries = 0;
+                    loop {
+                        tries += 1;
+                        let radius: u32 = self
+                            .rng
+                            .gen_rand(self.config.min_planet_radius, self.config.max_planet_radius);
+                        let reach = self.generate_moons(radius, max_reach, &mut moons);
+                        let eccentricity = self
+                            .rng
+                            .gen_rand(0, (self.config.max_eccentricity * 100.0) as u32)
+                            as f64
+                            / 100.0;
+                        let distance: u32 = self.rng.gen_rand(
+                            ((star_radius + reach) as f64 / (1.0 - eccentricity)).ceil() as u32,
+                            ((system_radius - reach) as f64 / (1.0 + eccentricity)) as u32,
+                        );
+                        let inner = distance as f64 * (1.0 - eccentricity) - reach as f64;
+                        let outer = distance as f64 * (1.0 + eccentricity) + reach as f64;
+                        let overlap = inner < star_radius as f64
+                            || outer > system_radius as f64
+                            || bands.iter().any(|&(other_inner, other_outer)| {
+                                inner < other_outer && other_inner < outer
+                            });
+                        if !overlap {
+                            bands.push((inner, outer));
+                            let planet = self.bodies.add_planet(star, radius, distance);
+                            let periapsis = self.rng.gen_rand(0, 359) as f64;
+                            self.bodies.set_orbit(planet, eccentricity, periapsis);
+                            for &(moon_radius, moon_distance) in moons.iter() {
+                                self.bodies.add_moon(planet, moon_radius, moon_distance);
+                            }
+                            break;
+                        } else if tries == self.config.max_tries {
+                            generation_done = false;
+                            break;
+                        }
                     }
+                    if !generation_done {
+                        break;
+                    }
+                }
+                if generation_done {
                     break;
                 }
+                if outer_tries == self.config.max_tries {
+                    return Err(GenerationError::PlanetPlacement { star: index });
+                }
+                self.bodies.truncate_planets(system_index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the moons of a planet of the given radius as (radius, distance)
+    /// pairs, with each moon orbit clear of the capture areas of the planet
+    /// and the moons further in. Returns the distance from the planet that
+    /// the capture areas of the planet and its moons reach, which is kept
+    /// within `max_reach` by dropping the outermost moons.
+    fn generate_moons(&mut self, radius: u32, max_reach: u32, moons: &mut Vec<(u32, u32)>) -> u32 {
+        moons.clear();
+        let config = self.config;
+        let mut reach = config.planet_activate_range * radius;
+        if config.max_num_moons == 0 || self.rng.gen_rand(1, 100) > config.moon_chance {
+            return reach;
+        }
+        for _ in 0..self.rng.gen_rand(1, config.max_num_moons) {
+            let moon_radius = self
+                .rng
+                .gen_rand(config.min_moon_radius, config.max_moon_radius);
+            let distance = reach
+                + config.planet_activate_range * moon_radius
+                + self.rng.gen_rand(0, config.moon_orbit_spread);
+            let moon_reach = distance + config.planet_activate_range * moon_radius;
+            if moon_reach > max_reach {
+                break;
             }
+            moons.push((moon_radius, distance));
+            reach = moon_reach;
         }
-        let mut data: Vec<u32> = Vec::new();
+        reach
+    }
+
+    pub(crate) fn viewport_width(&self) -> u32 {
+        self.viewport_width
+    }
+
+    pub(crate) fn viewport_height(&self) -> u32 {
+        self.viewport_height
+    }
+
+    fn push_point(&self, data: &mut Vec<u32>, x: f64, y: f64) {
+        let scale = self.view_scale * self.zoom;
+        let screen_x = (x - self.camera_x) * scale + self.viewport_width as f64 / 2.0;
+        let screen_y = (y - self.camera_y) * scale + self.viewport_height as f64 / 2.0;
+        data.push(screen_x as i32 as u32);
+        data.push(screen_y as i32 as u32);
+    }
+
+    fn push_circle(&self, data: &mut Vec<u32>, x: f64, y: f64, radius: f64) {
+        self.push_point(data, x, y);
+        data.push((radius * self.view_scale * self.zoom) as u32);
+    }
+
+    /// Writes the frame data described in [`Universe::tick`] into `data`.
+    pub(crate) fn build_frame(&self, data: &mut Vec<u32>) {
+        data.clear();
         data.push(0);
         data.push(self.score);
+        data.push(self.packet_ttl);
+        data.push(self.packet_size);
+        data.push(self.packet_hold);
+        data.push(self.energy.max(0.0).ceil() as u32);
+        data.push(self.config.energy_budget);
+        // Push orbits and star system boundaries
+        if self.show_orbits {
+            data.push(self.num_planets() as u32);
+            for planet in self.bodies.planets() {
+                let (x, y) = planet.ellipse_center();
+                self.push_point(data, x, y);
+                let scale = self.view_scale * self.zoom;
+                data.push((planet.distance() as f64 * scale) as u32);
+                data.push((planet.semi_minor_axis() * scale) as u32);
+                data.push(planet.periapsis() as i32 as u32);
+            }
+        } else {
+            data.push(0);
+        }
+        if self.show_system_boundaries {
+            data.push(self.num_stars() as u32);
+            for star in self.bodies.stars() {
+                self.push_circle(
+                    data,
+                    star.x() as f64,
+                    star.y() as f64,
+                    star.system_radius() as f64,
+                );
+            }
+        } else {
+            data.push(0);
+        }
+        // Push packet trail and the route taken in this round
+        data.push(self.packet_trail.len() as u32);
+        for (x, y) in self.packet_trail.iter() {
+            self.push_point(data, *x, *y);
+        }
+        data.push(self.route.len() as u32);
+        for (_, x, y) in self.route.iter() {
+            self.push_point(data, *x, *y);
+        }
         // Push packet or active planet data
         match self.packet {
             Packet::Bound {
@@ -541,14 +2035,13 @@ impl Universe {
                 dq: _,
                 direction: _,
             } => {
-                data.push(self.planet_x(planet) as u32);
-                data.push(self.planet_y(planet) as u32);
-                data.push(self.packet_end_x() as u32);
-                data.push(self.packet_end_y() as u32);
+                let (x, y) = (self.planet_x(planet), self.planet_y(planet));
                 data.push(1);
-                data.push(self.planet_x(planet) as u32);
-                data.push(self.planet_y(planet) as u32);
-                data.push((self.planet_radius[planet] as f32 * 1.5) as u32);
+                self.push_point(data, x, y);
+                self.push_point(data, self.packet_end_x(), self.packet_end_y());
+                data.push(1);
+                let radius = self.bodies.planet(planet).radius();
+                self.push_circle(data, x, y, radius as f64 * 1.5);
             }
             Packet::Free {
                 x,
@@ -559,30 +2052,113 @@ impl Universe {
             } => {
                 data.push(0);
                 data.push(1);
-                data.push(x as u32);
-                data.push(y as u32);
-                data.push(PACKET_RADIUS);
+                self.push_circle(data, x, y, self.config.packet_radius as f64);
             }
         }
         // Push source and destination planet
         data.push(2);
-        data.push(self.planet_x(self.packet_destination) as u32);
-        data.push(self.planet_y(self.packet_destination) as u32);
-        data.push((self.planet_radius[self.packet_destination] as f32 * 1.8) as u32);
-        data.push(self.planet_x(self.packet_source) as u32);
-        data.push(self.planet_y(self.packet_source) as u32);
-        data.push((self.planet_radius[self.packet_source] as f32 * 1.8) as u32);
+        for planet in [self.packet_destination, self.packet_source].iter() {
+            let planet = self.bodies.planet(*planet);
+            self.push_circle(data, planet.x(), planet.y(), planet.radius() as f64 * 1.8);
+        }
         // Push star data
-        data.push(self.star_x.len() as u32);
-        for i in 0..self.star_x.len() {
-            data.push(self.star_x[i]);
-            data.push(self.star_y[i]);
-            data.push(self.star_radius[i]);
+        data.push(self.num_stars() as u32);
+        for star in self.bodies.stars() {
+            self.push_circle(data, star.x() as f64, star.y() as f64, star.radius() as f64);
         }
         // Push planet data
-        data.push(self.planet_q.len() as u32);
-        data.append(&mut planet_data);
-        data.as_ptr()
+        data.push(self.num_planets() as u32);
+        for planet in self.bodies.planets() {
+            self.push_circle(data, planet.x(), planet.y(), planet.radius() as f64);
+        }
+        // Push planets that would drop the packet, and planets that are
+        // offline
+        let count_index = data.len();
+        data.push(0);
+        for planet in self.bodies.planets() {
+            if planet.queue_capacity() < self.packet_size {
+                self.push_circle(data, planet.x(), planet.y(), planet.radius() as f64);
+                data[count_index] += 1;
+            }
+        }
+        let count_index = data.len();
+        data.push(0);
+        for planet in self.bodies.planets() {
+            if !planet.is_online(self.time) {
+                self.push_circle(data, planet.x(), planet.y(), planet.radius() as f64);
+                data[count_index] += 1;
+            }
+        }
+        // Push markers for the kinds of special planets
+        let count_index = data.len();
+        data.push(0);
+        for planet in self.bodies.planets() {
+            if planet.kind() != PlanetKind::Normal {
+                self.push_circle(data, planet.x(), planet.y(), planet.radius() as f64);
+                data.push(planet.kind() as u32);
+                data[count_index] += 1;
+            }
+        }
+        // Push the debug overlay
+        if self.debug {
+            data.push(1);
+            data.push(self.generation_attempts);
+            data.push(
+                self.packet
+                    .get_last_planet()
+                    .map_or(-1, |planet| planet.index() as i32) as u32,
+            );
+            let (x, y) = self.packet_position();
+            let (dx, dy) = self.packet.velocity();
+            self.push_point(data, x, y);
+            self.push_point(
+                data,
+                x + dx * DEBUG_VELOCITY_TICKS,
+                y + dy * DEBUG_VELOCITY_TICKS,
+            );
+            data.push(self.num_planets() as u32);
+            for planet in self.bodies.planets() {
+                self.push_circle(
+                    data,
+                    planet.x(),
+                    planet.y(),
+                    self.capture_radius(planet.id()),
+                );
+                data.push(planet.id().index() as u32);
+            }
+        } else {
+            data.push(0);
+        }
+        data[0] = data.len() as u32;
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn throw_generation_error(err: GenerationError) -> ! {
+    throw_str(&err.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn throw_generation_error(err: GenerationError) -> ! {
+    panic!("{}", err)
+}
+
+fn clamp_axis(center: f64, half_extent: f64, world_extent: f64) -> f64 {
+    // A NaN extent or center would make the clamp panic or stick
+    if !half_extent.is_finite() || !center.is_finite() || world_extent <= half_extent * 2.0 {
+        world_extent / 2.0
+    } else {
+        center.clamp(half_extent, world_extent - half_extent)
+    }
+}
+
+/// Makes the planets of a round smaller, then shortens their capture
+/// range, down to the smallest relaxed rules.
+fn shrink_planets(config: &mut GameConfig) {
+    if config.max_planet_radius > config.min_planet_radius {
+        config.max_planet_radius -= 1;
+    } else if config.planet_activate_range > MIN_RELAXED_ACTIVATE_RANGE {
+        config.planet_activate_range -= 1;
     }
 }
 
@@ -590,3 +2166,114 @@ fn circles_overlap(x1: u32, y1: u32, radius1: u32, x2: u32, y2: u32, radius2: u3
     (x1 as i32 - x2 as i32).pow(2) + (y1 as i32 - y2 as i32).pow(2)
         < (radius1 + radius2).pow(2) as i32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_moons_moved_below_their_parent() {
+        let mut universe = Universe::with_seed(1280, 720, 9);
+        assert!(universe.remove_planet(PlanetId::new(0)));
+        let parent = universe
+            .bodies()
+            .planets()
+            .find(|planet| planet.moons().any(|moon| moon.id() < planet.id()))
+            .map(|planet| planet.id())
+            .unwrap();
+        let num_planets = universe.bodies().num_planets();
+        let num_moons = universe.bodies().planet(parent).moons().count();
+        assert!(universe.remove_planet(parent));
+        assert_eq!(universe.bodies().num_planets(), num_planets - 1 - num_moons);
+        assert!(moons_orbit_their_star(&universe));
+    }
+
+    #[test]
+    fn removes_star_with_moons_moved_below_their_parent() {
+        let mut removed = 0;
+        for seed in 1..40 {
+            let mut universe = Universe::with_seed(1280, 720, seed);
+            universe.remove_planet(PlanetId::new(0));
+            let star = universe
+                .bodies()
+                .planets()
+                .find(|planet| planet.moons().any(|moon| moon.id() < planet.id()))
+                .map(|planet| planet.star().id());
+            if let Some(star) = star {
+                if universe.remove_star(star) {
+                    removed += 1;
+                }
+            }
+            assert!(moons_orbit_their_star(&universe));
+        }
+        assert!(removed > 0);
+    }
+
+    #[test]
+    fn keeps_previous_route_when_round_cannot_be_generated() {
+        let config = GameConfig {
+            sink_chance: 60,
+            link_window_chance: 60,
+            ..GameConfig::default()
+        };
+        // A single star system with a single planet and no moons fits in
+        // the world
+        let unplaceable = GameConfig {
+            min_star_system_radius: 360,
+            max_star_system_radius: 360,
+            min_num_planets: 1,
+            max_num_planets: 1,
+            max_num_moons: 0,
+            ..config
+        };
+        for seed in 1..10 {
+            let mut universe = Universe::with_config(1280, 720, 1280, 720, seed, config);
+            for _ in 0..200 {
+                universe.tick();
+            }
+            let route = (universe.packet_source, universe.packet_destination);
+            universe.set_config(unplaceable);
+            universe.new_game();
+            assert_eq!(universe.generation_attempts(), MAX_GENERATION_ATTEMPTS);
+            assert_eq!((universe.packet_source, universe.packet_destination), route);
+            for planet in [route.0, route.1] {
+                let planet = universe.bodies().planet(planet);
+                assert_eq!(planet.kind(), PlanetKind::Normal);
+                assert_eq!(planet.link_window(), LinkWindow::Always);
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_nan_time_scale() {
+        let mut universe = Universe::with_seed(1280, 720, 1);
+        universe.set_time_scale(f64::NAN);
+        universe.tick();
+        assert_eq!(universe.game_ticks(), 1);
+        assert_eq!(universe.time_scale(), 1.0);
+    }
+
+    #[test]
+    fn keeps_config_that_does_not_fit() {
+        let mut universe = Universe::with_seed(200, 200, 1);
+        let config = GameConfig {
+            max_planet_radius: 30,
+            ..GameConfig::default()
+        };
+        assert!(matches!(
+            universe.try_set_config(config),
+            Err(GenerationError::WorldTooSmall { .. })
+        ));
+        assert_eq!(universe.config(), GameConfig::default());
+        universe.new_game();
+        assert!(universe.bodies().num_planets() >= 2);
+    }
+
+    fn moons_orbit_their_star(universe: &Universe) -> bool {
+        universe.bodies().planets().all(|planet| {
+            planet
+                .parent()
+                .is_none_or(|parent| parent.star().id() == planet.star().id())
+        })
+    }
+}
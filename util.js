@@ -3,3 +3,20 @@ export function gen_rand(range_start, range_end) {
     Math.floor(Math.random() * (range_end - range_start) + 1) + range_start
   );
 }
+
+export function load_item(key) {
+  try {
+    return localStorage.getItem(key);
+  } catch (e) {
+    return null;
+  }
+}
+
+export function save_item(key, value) {
+  try {
+    localStorage.setItem(key, value);
+    return true;
+  } catch (e) {
+    return false;
+  }
+}